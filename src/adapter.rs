@@ -1,19 +1,46 @@
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use flate2::write::GzEncoder;
 use indexmap::IndexMap;
+use regex::{Regex, RegexBuilder};
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json;
-use std::{collections::HashMap, fs, path::Path};
+use sha2::{Digest, Sha256};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 use tanu_core::{
-    eyre, http,
+    eyre::{self, WrapErr},
+    http,
     runner::{self, Test},
     ModuleName, ProjectName, Reporter, TestName,
 };
 
 use crate::models::{
-    generate_history_id, History, HistoryItem, HistoryTime, Label, Parameter, ParameterMode, Stage,
-    Status, StatusDetails, Step, TestResult, MAX_HISTORY_ITEMS,
+    generate_history_id_with, generate_test_case_id, unix_millis, Attachment, Category,
+    DurationTrendData, Executor, FixtureResult, HashAlgo, History, HistoryIdConfig, HistoryItem,
+    HistoryTime, Label, Link, Parameter, ParameterMode, RetryTrendData, Stage, Status,
+    StatusDetails, Step, TestResult, TestResultContainer, TrendItem, MAX_HISTORY_ITEMS,
+    MAX_TREND_ITEMS,
 };
+use crate::output;
+use crate::sink::{self, FilesystemSink, ResultSink};
 
-fn to_status(status: http::StatusCode) -> Status {
-    if status.is_success() {
+/// Maps an HTTP response status to a step status: 2xx and 3xx pass, 4xx and 5xx fail and
+/// codes outside the standard classes are broken.
+///
+/// The log holds the final response, so a 3xx only shows up when the client did not follow
+/// the redirect, which is a valid outcome rather than an error. 1xx responses are not
+/// failures by themselves either (e.g. `101 Switching Protocols` for a WebSocket upgrade),
+/// so they map to the configurable `informational` status.
+pub fn http_status(status: http::StatusCode, informational: &Status) -> Status {
+    if status.is_informational() {
+        informational.clone()
+    } else if status.is_success() || status.is_redirection() {
         Status::Passed
     } else if status.is_client_error() || status.is_server_error() {
         Status::Failed
@@ -22,41 +49,541 @@ fn to_status(status: http::StatusCode) -> Status {
     }
 }
 
-fn to_test_status(test: &Test) -> Status {
+impl From<http::StatusCode> for Status {
+    /// Maps the status as [`http_status`] does with the reporter's default, passing 1xx.
+    fn from(status: http::StatusCode) -> Self {
+        http_status(status, &Status::Passed)
+    }
+}
+
+/// The default mapping from a finished test to its status: passed, or as
+/// [`default_error_status`] maps its error.
+pub fn default_test_status(test: &Test) -> Status {
     match &test.result {
         Ok(_) => Status::Passed,
-        Err(runner::Error::ErrorReturned(_)) => Status::Failed,
-        Err(runner::Error::Panicked(_)) => Status::Broken,
+        Err(e) => default_error_status(e),
+    }
+}
+
+/// The default mapping from a test error to its status, used unless
+/// [`AllureReporter::with_error_mapper`] replaces it.
+pub fn default_error_status(error: &runner::Error) -> Status {
+    #[allow(unreachable_patterns)]
+    match error {
+        runner::Error::ErrorReturned(_) => Status::Failed,
+        runner::Error::Panicked(_) => Status::Broken,
+        // Error kinds added to tanu later are broken until they are mapped explicitly
+        _ => Status::Broken,
+    }
+}
+
+/// Sections eyre appends to an error report after the message
+const ERROR_TRACE_SECTIONS: &[&str] = &["\n\nLocation:", "\n\nStack backtrace:", "\n\nBacktrace"];
+
+fn to_status_details(test: &Test) -> Option<StatusDetails> {
+    if let Err(e) = &test.result {
+        let (message, trace) = split_error_report(&strip_ansi_escapes::strip_str(e.to_string()));
+        Some(StatusDetails {
+            known: None,
+            muted: None,
+            flaky: None,
+            message: Some(message),
+            trace,
+        })
+    } else {
+        None
+    }
+}
+
+/// Splits an eyre report into its message and the location/backtrace sections that
+/// follow it, which belong in `statusDetails.trace`
+fn split_error_report(report: &str) -> (String, Option<String>) {
+    let split_at = ERROR_TRACE_SECTIONS
+        .iter()
+        .filter_map(|section| report.find(section))
+        .min();
+    match split_at {
+        Some(at) => {
+            let trace = report[at..].trim();
+            (report[..at].trim_end().to_string(), Some(trace.to_string()))
+        }
+        None => (report.to_string(), None),
+    }
+}
+
+/// Prepends the newest run to a trend, dropping the oldest beyond [`MAX_TREND_ITEMS`]
+fn push_trend<T>(trend: &mut Vec<TrendItem<T>>, item: TrendItem<T>) {
+    trend.insert(0, item);
+    trend.truncate(MAX_TREND_ITEMS);
+}
+
+/// Returns the environment a test ran against: the project's configured `base_url`,
+/// or the origin of the first HTTP call when no base URL is configured
+fn target_environment(project: &str, events: &[Event]) -> Option<String> {
+    let base_url = tanu_core::get_tanu_config()
+        .projects
+        .iter()
+        .find(|p| p.name == project)
+        .and_then(|p| p.get_str("base_url").ok().map(str::to_string));
+
+    base_url.or_else(|| {
+        events.iter().find_map(|event| match event {
+            Event::Http(log) => Some(log.request.url.origin().ascii_serialization()),
+            Event::Check(_) => None,
+        })
+    })
+}
+
+/// Prepends a result to its history entry, updating the statistics and trimming old items
+fn record_history(history: &mut History, result: &RunResult) {
+    let entry = history.entry(result.history_id.clone()).or_default();
+
+    // Muted runs are kept as items but, as in Allure's own statistics, not counted
+    if !result.muted {
+        entry.statistic.record(&result.status);
+    }
+
+    // Add new history item at the beginning
+    entry.items.insert(
+        0,
+        HistoryItem {
+            uid: result.uuid.to_string(),
+            report_url: None,
+            status: result.status.clone(),
+            status_details: result.status_details.clone(),
+            time: HistoryTime {
+                start: result.start,
+                stop: result.stop,
+                duration: result.stop - result.start,
+            },
+        },
+    );
+
+    // Trim to max items
+    entry.items.truncate(MAX_HISTORY_ITEMS);
+}
+
+/// Widens the container's start/stop so it covers the given span
+fn extend_span(container: &mut TestResultContainer, start: Option<i64>, stop: Option<i64>) {
+    container.start = match (container.start, start) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    container.stop = match (container.stop, stop) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+}
+
+/// Splits a check expression into its first line and the rendered values that follow it
+fn split_check_expr(expr: &str) -> (&str, &str) {
+    match expr.split_once('\n') {
+        Some((headline, detail)) => (headline.trim_end(), detail.trim()),
+        None => (expr, ""),
+    }
+}
+
+/// Returns the media type of a `Content-Type` header without its parameters
+fn content_type(headers: &http::header::HeaderMap) -> Option<&str> {
+    headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Size of a logged body, or `None` when it is empty without `Content-Length: 0` saying so,
+/// which is indistinguishable from a body that was not captured
+fn captured_size(headers: &http::header::HeaderMap, body: &str) -> Option<usize> {
+    let declared_empty = headers
+        .get(http::header::CONTENT_LENGTH)
+        .is_some_and(|value| value.as_bytes() == b"0");
+    (!body.is_empty() || declared_empty).then_some(body.len())
+}
+
+/// Whether a media type holds text, which compresses well, as opposed to already compact
+/// binary data
+fn is_textual(media_type: &str) -> bool {
+    let media_type = media_type.to_ascii_lowercase();
+    media_type.starts_with("text/")
+        || media_type.ends_with("+json")
+        || media_type.ends_with("+xml")
+        || matches!(
+            media_type.as_str(),
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/x-www-form-urlencoded"
+        )
+}
+
+/// Guesses the media type of a body sent without a meaningful `Content-Type` from its
+/// content: JSON if it parses as such, XML or HTML from its leading markup
+fn sniff_media_type(body: &str) -> Option<&'static str> {
+    let trimmed = body.trim_start();
+    if trimmed.starts_with(['{', '[']) && serde_json::from_str::<serde_json::Value>(body).is_ok() {
+        return Some("application/json");
+    }
+    let head = trimmed
+        .chars()
+        .take(16)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        Some("text/html")
+    } else if head.starts_with("<?xml") {
+        Some("application/xml")
+    } else {
+        None
+    }
+}
+
+/// The `httpVersion` of HAR entries, as tanu does not record the version a call used
+const HAR_UNKNOWN_HTTP_VERSION: &str = "unknown";
+
+/// Prefix of the lines written by [`AllureReporter::stdout_json`]
+pub const STDOUT_JSON_MARKER: &str = "ALLURE_RESULT ";
+
+/// Renders `result` as one marked line of compact JSON, ending in a newline
+fn json_line(result: &TestResult) -> eyre::Result<String> {
+    let mut line = STDOUT_JSON_MARKER.to_string();
+    line.push_str(&serde_json::to_string(result)?);
+    line.push('\n');
+    Ok(line)
+}
+
+/// Prints `result` as one marked line, written and flushed in one go so that output of
+/// concurrently running tests cannot end up in the middle of it
+fn write_json_line(result: &TestResult) -> eyre::Result<()> {
+    let line = json_line(result)?;
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(line.as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Distinct hosts the test sent HTTP calls to, in the order they were first called
+fn service_hosts(events: &[Event]) -> Vec<String> {
+    let mut hosts: Vec<String> = Vec::new();
+    for event in events {
+        if let Event::Http(log) = event {
+            if let Some(host) = log.request.url.host_str() {
+                if !hosts.iter().any(|seen| seen == host) {
+                    hosts.push(host.to_string());
+                }
+            }
+        }
+    }
+    hosts
+}
+
+/// Shown in place of an empty test name
+const UNNAMED_TEST: &str = "<unnamed test>";
+
+/// Returns the name to show for a test and the name its ids are derived from.
+///
+/// An empty or blank name is a misconfiguration; it is reported as [`UNNAMED_TEST`] and
+/// identified by its source line so that its history stays apart from other unnamed tests.
+/// The warning is printed the first time a test is seen in `warned`, not for every attempt.
+fn display_test_name(
+    project: &str,
+    module: &str,
+    test_name: &str,
+    test: &Test,
+    warned: &Mutex<HashSet<String>>,
+) -> (String, String) {
+    if !test_name.trim().is_empty() {
+        return (test_name.to_string(), test_name.to_string());
+    }
+    let line = test.info.line;
+    let mut warned = warned.lock().unwrap_or_else(|e| e.into_inner());
+    if warned.insert(format!("{project}::{module}@{line}")) {
+        eprintln!(
+            "tanu-allure: test at {project}::{module} line {line} has an empty name, reporting it as \"{UNNAMED_TEST}\""
+        );
+    }
+    (UNNAMED_TEST.to_string(), format!("{UNNAMED_TEST}@{line}"))
+}
+
+/// Severity levels Allure knows, from most to least severe
+const SEVERITIES: &[&str] = &["blocker", "critical", "normal", "minor", "trivial"];
+
+/// Returns `severity` if Allure knows it, `normal` otherwise
+fn known_severity(severity: &str, test_name: &str) -> String {
+    if SEVERITIES.contains(&severity) {
+        severity.to_string()
+    } else {
+        eprintln!(
+            "tanu-allure: unknown severity \"{severity}\" for test \"{test_name}\", using \"normal\""
+        );
+        "normal".to_string()
+    }
+}
+
+/// Renders a Markdown description for `descriptionHtml`.
+///
+/// Raw HTML in the description is escaped and `javascript:` links are dropped, since the
+/// report shows the result as-is.
+#[cfg(feature = "markdown")]
+fn markdown_to_html(markdown: &str) -> Option<String> {
+    use pulldown_cmark::{html, CowStr, Event as MdEvent, Parser, Tag};
+
+    let events = Parser::new(markdown).map(|event| match event {
+        MdEvent::Html(raw) | MdEvent::InlineHtml(raw) => MdEvent::Text(raw),
+        MdEvent::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if is_script_url(&dest_url) => MdEvent::Start(Tag::Link {
+            link_type,
+            dest_url: CowStr::Borrowed("#"),
+            title,
+            id,
+        }),
+        MdEvent::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if is_script_url(&dest_url) => MdEvent::Start(Tag::Image {
+            link_type,
+            dest_url: CowStr::Borrowed(""),
+            title,
+            id,
+        }),
+        event => event,
+    });
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, events);
+    Some(rendered)
+}
+
+#[cfg(feature = "markdown")]
+fn is_script_url(url: &str) -> bool {
+    let scheme = url.trim_start().split(':').next().unwrap_or_default();
+    url.contains(':')
+        && ["javascript", "vbscript", "data"]
+            .iter()
+            .any(|script| scheme.eq_ignore_ascii_case(script))
+}
+
+/// Without the `markdown` feature only the Markdown description is written
+#[cfg(not(feature = "markdown"))]
+fn markdown_to_html(_markdown: &str) -> Option<String> {
+    None
+}
+
+/// Maps a module path such as `epic_checkout::feature_cart::story_add_item` to BDD labels.
+///
+/// Segments prefixed with `epic_`, `feature_` or `story_` become the corresponding label;
+/// the remaining segments, joined with `::`, form the suite.
+fn module_labels(module: &str) -> Vec<Label> {
+    let mut labels = Vec::new();
+    let mut suite = Vec::new();
+    for segment in module.split("::") {
+        if let Some(epic) = segment.strip_prefix("epic_") {
+            labels.push(Label::Epic(epic.to_string()));
+        } else if let Some(feature) = segment.strip_prefix("feature_") {
+            labels.push(Label::Feature(feature.to_string()));
+        } else if let Some(story) = segment.strip_prefix("story_") {
+            labels.push(Label::Story(story.to_string()));
+        } else {
+            suite.push(segment);
+        }
+    }
+    if !suite.is_empty() {
+        labels.insert(0, Label::Suite(suite.join("::")));
+    }
+    labels
+}
+
+/// Whether a tag configures the reporter rather than describing the test
+fn is_reporter_tag(tag: &str) -> bool {
+    tag.starts_with("expect-status:") || tag.starts_with("known_issue:") || tag == "muted"
+}
+
+/// Collects the values of the `known_issue:<issue>` tags
+fn known_issues(tags: &[String]) -> Vec<&str> {
+    tags.iter()
+        .filter_map(|tag| tag.strip_prefix("known_issue:"))
+        .filter(|issue| !issue.is_empty())
+        .collect()
+}
+
+/// Collects the status codes of the `expect-status:<code>` tags, ignoring malformed ones
+fn expected_statuses(tags: &[String]) -> Vec<u16> {
+    tags.iter()
+        .filter_map(|tag| tag.strip_prefix("expect-status:"))
+        .filter_map(|code| code.trim().parse().ok())
+        .collect()
+}
+
+/// Nests consecutive HTTP calls to the same method and host, and the checks that follow
+/// them, under one parent step. Checks before the first call stay at the top level.
+fn group_steps(steps: Vec<(Option<String>, Step)>) -> Vec<Step> {
+    let mut grouped = Vec::new();
+    let mut current: Option<(String, Vec<Step>)> = None;
+    for (group, step) in steps {
+        match (group, &mut current) {
+            (Some(group), Some((name, children))) if *name == group => children.push(step),
+            (Some(group), _) => {
+                if let Some((name, children)) = current.replace((group, vec![step])) {
+                    grouped.push(Step::group(name, children));
+                }
+            }
+            (None, Some((_, children))) => children.push(step),
+            (None, None) => grouped.push(step),
+        }
+    }
+    if let Some((name, children)) = current {
+        grouped.push(Step::group(name, children));
+    }
+    grouped
+}
+
+/// Records on a coalesced HTTP step how many identical calls it stands for
+fn set_call_count(step: &mut Step, count: usize) {
+    let value = count.to_string();
+    match step.parameters.iter_mut().find(|p| p.name == "count") {
+        Some(parameter) => parameter.value = value,
+        None => step.parameters.insert(
+            0,
+            Parameter {
+                name: "count".to_string(),
+                value,
+                excluded: None,
+                mode: None,
+            },
+        ),
+    }
+}
+
+/// Header names masked unless replaced with [`AllureReporter::with_masked_headers`]
+const DEFAULT_MASKED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "x-auth-token",
+];
+
+/// Query parameter names masked unless more are added with
+/// [`AllureReporter::add_masked_query_param`]
+const DEFAULT_MASKED_QUERY_PARAMS: &[&str] = &[
+    "token",
+    "access_token",
+    "api_key",
+    "apikey",
+    "key",
+    "password",
+    "secret",
+    "client_secret",
+    "signature",
+];
+
+/// Decides which header values are replaced with `<masked>` in step parameters
+struct HeaderMasking {
+    /// Lowercased header names to mask
+    names: HashSet<String>,
+    /// Additional header name patterns, anchored and matched case-insensitively
+    patterns: Vec<Regex>,
+}
+
+impl Default for HeaderMasking {
+    fn default() -> Self {
+        HeaderMasking {
+            names: DEFAULT_MASKED_HEADERS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            patterns: Vec::new(),
+        }
     }
 }
 
-fn system_time_to_unix_millis(time: std::time::SystemTime) -> i64 {
-    time.duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as i64
+impl HeaderMasking {
+    fn is_sensitive(&self, header_name: &str) -> bool {
+        self.names.contains(&header_name.to_ascii_lowercase())
+            || self.patterns.iter().any(|p| p.is_match(header_name))
+    }
+}
+
+/// Decides which values of JSON bodies are replaced with `***` before they are attached
+#[derive(Default)]
+struct BodyRedaction {
+    /// Lowercased object keys to redact
+    keys: HashSet<String>,
+    /// Additional key patterns, anchored and matched case-insensitively
+    patterns: Vec<Regex>,
+}
+
+impl BodyRedaction {
+    fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.patterns.is_empty()
+    }
+
+    fn is_sensitive(&self, key: &str) -> bool {
+        self.keys.contains(&key.to_ascii_lowercase())
+            || self.patterns.iter().any(|p| p.is_match(key))
+    }
+
+    /// Replaces the values of sensitive keys at any depth, returning whether any was found
+    fn redact(&self, value: &mut serde_json::Value) -> bool {
+        let mut redacted = false;
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self.is_sensitive(key) {
+                        *value = serde_json::Value::String("***".to_string());
+                        redacted = true;
+                    } else {
+                        redacted |= self.redact(value);
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    redacted |= self.redact(item);
+                }
+            }
+            _ => {}
+        }
+        redacted
+    }
 }
 
+/// Default for [`AllureReporter::max_header_bytes`]
+const DEFAULT_MAX_HEADER_BYTES: usize = 1024;
+
+/// Appended to header values cut off at `max_header_bytes`
+const TRUNCATED_SUFFIX: &str = "…(truncated)";
+
 fn push_header_parameters(
     parameters: &mut Vec<Parameter>,
     prefix: &str,
     headers: &http::header::HeaderMap,
+    masking: &HeaderMasking,
+    max_value_bytes: usize,
 ) {
     for (name, value) in headers.iter() {
         let header_name = name.as_str();
-        let is_sensitive = matches!(
-            header_name,
-            "authorization"
-                | "proxy-authorization"
-                | "cookie"
-                | "set-cookie"
-                | "x-api-key"
-                | "x-auth-token"
-        );
 
-        let (value, mode) = if is_sensitive {
+        let (value, mode) = if masking.is_sensitive(header_name) {
             ("<masked>".to_string(), Some(ParameterMode::Masked))
         } else {
-            (String::from_utf8_lossy(value.as_bytes()).into_owned(), None)
+            let mut value = header_value_text(value).into_owned();
+            if value.len() > max_value_bytes {
+                let mut end = max_value_bytes;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                value.truncate(end);
+                value.push_str(TRUNCATED_SUFFIX);
+            }
+            (value, None)
         };
 
         parameters.push(Parameter {
@@ -68,22 +595,371 @@ fn push_header_parameters(
     }
 }
 
+/// Adds the method, status, body sizes and query string of an HTTP call as parameters
+fn push_http_parameters(
+    parameters: &mut Vec<Parameter>,
+    log: &http::Log,
+    masked_query_params: &HashSet<String>,
+) {
+    let status = log.response.status;
+    parameters.extend([
+        Parameter {
+            name: "request.method".to_string(),
+            value: log.request.method.to_string(),
+            excluded: None,
+            mode: None,
+        },
+        Parameter {
+            name: "response.status".to_string(),
+            value: match status.canonical_reason() {
+                Some(reason) => format!("{} {reason}", status.as_u16()),
+                None => status.as_u16().to_string(),
+            },
+            excluded: None,
+            mode: None,
+        },
+    ]);
+    // Sizes of the bodies as sent and received, before truncation and redaction
+    let sizes = [
+        (
+            "request.bytes",
+            log.request
+                .body
+                .as_deref()
+                .and_then(|body| captured_size(&log.request.headers, body)),
+        ),
+        (
+            "response.bytes",
+            captured_size(&log.response.headers, &log.response.body),
+        ),
+    ];
+    for (name, size) in sizes {
+        if let Some(size) = size {
+            parameters.push(Parameter {
+                name: name.to_string(),
+                value: size.to_string(),
+                excluded: None,
+                mode: None,
+            });
+        }
+    }
+    push_query_parameters(parameters, &log.request.url, masked_query_params);
+}
+
+/// Adds a `request.query.<key>` parameter for every pair in the query string, masking the
+/// values of the names in `masked` (lowercased)
+fn push_query_parameters(
+    parameters: &mut Vec<Parameter>,
+    url: &url::Url,
+    masked: &HashSet<String>,
+) {
+    for (name, value) in url.query_pairs() {
+        let (value, mode) = if masked.contains(&name.to_ascii_lowercase()) {
+            ("<masked>".to_string(), Some(ParameterMode::Masked))
+        } else {
+            (value.into_owned(), None)
+        };
+        parameters.push(Parameter {
+            name: format!("request.query.{name}"),
+            value,
+            excluded: None,
+            mode,
+        });
+    }
+}
+
+/// Renders a header value as text, spelling out values that are not UTF-8 in hex instead of
+/// replacing their bytes with U+FFFD
+fn header_value_text(value: &http::header::HeaderValue) -> Cow<'_, str> {
+    match std::str::from_utf8(value.as_bytes()) {
+        Ok(text) => Cow::Borrowed(text),
+        Err(_) => {
+            let hex: String = value
+                .as_bytes()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect();
+            Cow::Owned(format!("<non-UTF-8, hex: {hex}>"))
+        }
+    }
+}
+
+/// Lists headers as HAR `{name, value}` pairs, masking sensitive values
+fn har_headers(headers: &http::header::HeaderMap, masking: &HeaderMasking) -> serde_json::Value {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if masking.is_sensitive(name.as_str()) {
+                "<masked>".into()
+            } else {
+                header_value_text(value)
+            };
+            serde_json::json!({ "name": name.as_str(), "value": value })
+        })
+        .collect()
+}
+
+/// Quotes `text` for a POSIX shell
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Builds a `curl` command line that repeats the request with `body`, masking sensitive
+/// headers
+fn curl_command(request: &http::LogRequest, body: Option<&str>, masking: &HeaderMasking) -> String {
+    let mut command = format!(
+        "curl -X {} {}",
+        request.method,
+        shell_quote(request.url.as_str())
+    );
+    for (name, value) in request.headers.iter() {
+        let value = if masking.is_sensitive(name.as_str()) {
+            "<masked>".into()
+        } else {
+            header_value_text(value)
+        };
+        command.push_str(" \\\n  -H ");
+        command.push_str(&shell_quote(&format!("{name}: {value}")));
+    }
+    if let Some(body) = body {
+        command.push_str(" \\\n  --data-raw ");
+        command.push_str(&shell_quote(body));
+    }
+    command
+}
+
+/// Converts the bounds of a test or call, never letting it end before it started even if
+/// the clock was set back in between
+fn time_span(started_at: std::time::SystemTime, ended_at: std::time::SystemTime) -> (i64, i64) {
+    let start = unix_millis(started_at);
+    (start, unix_millis(ended_at).max(start))
+}
+
+/// Formats a timestamp as an RFC 3339 UTC date-time with milliseconds, as HAR requires
+fn to_rfc3339(time: std::time::SystemTime) -> String {
+    let millis = unix_millis(time);
+    let (days, millis_of_day) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        millis_of_day / 3_600_000,
+        millis_of_day / 60_000 % 60,
+        millis_of_day / 1000 % 60,
+        millis_of_day % 1000
+    )
+}
+
+/// A tanu reporter that writes Allure result files into `results_dir`.
+///
+/// tanu delivers the events of all tests to a reporter one at a time through `&mut self`,
+/// so the per-test buffers need no locking. A test's buffer lives from its `on_start` until
+/// its `on_end`. To keep disk I/O off the event path, wrap the reporter in a
+/// [`ChannelReporter`](crate::ChannelReporter).
 pub struct AllureReporter {
     pub results_dir: String,
     buffer: IndexMap<(ProjectName, ModuleName, TestName), Buffer>,
     history: History,
     current_run_results: Vec<RunResult>,
     environment: HashMap<String, String>,
+    header_masking: HeaderMasking,
+    masked_query_params: HashSet<String>,
+    body_redaction: BodyRedaction,
+    max_body_bytes: Option<u64>,
+    max_header_bytes: usize,
+    attachment_compression: Option<usize>,
+    inline_attachments: Option<usize>,
+    test_case_id_algo: HashAlgo,
+    fixture_fn: Option<FixtureFn>,
+    containers: IndexMap<(ProjectName, ModuleName), TestResultContainer>,
+    finish_fn: Option<FinishFn>,
+    uuid_fn: UuidFn,
+    print_summary: bool,
+    informational_status: Status,
+    attach_output: bool,
+    incremental_history: bool,
+    shared_results_dir: bool,
+    error_mapper: ErrorMapper,
+    environment_label: bool,
+    environment_in_history_id: bool,
+    attach_timeline: bool,
+    skip_body_types: Vec<String>,
+    media_type_overrides: HashMap<String, String>,
+    compression: Compression,
+    coalesce_http_calls: bool,
+    tags_fn: Option<TagsFn>,
+    start_order_parameter: bool,
+    next_start_order: u64,
+    stdout_json: bool,
+    #[cfg(feature = "json-schema")]
+    response_schemas: Vec<(Regex, jsonschema::Validator)>,
+    version_label: bool,
+    history_id_config: HistoryIdConfig,
+    attach_har: bool,
+    project_parameter: bool,
+    host_thread_labels: bool,
+    header_parameters: bool,
+    http_parameters: bool,
+    service_labels: bool,
+    categories: Vec<Category>,
+    severity_fn: Option<SeverityFn>,
+    default_severity: Option<String>,
+    clean: bool,
+    /// Whether the one-time setup of the results directory has run
+    initialized: bool,
+    /// Why the configuration was rejected or the results destination could not be
+    /// prepared; nothing is written after that
+    prepare_error: Option<String>,
+    executor: Option<Executor>,
+    step_grouping: bool,
+    description_fn: Option<DescriptionFn>,
+    links_fn: Option<LinksFn>,
+    bdd_labels: bool,
+    global_labels: Vec<Label>,
+    results_index: bool,
+    issue_url_template: Option<String>,
+    flaky_history_window: Option<usize>,
+    curl_attachments: bool,
+    pretty: bool,
+    duration_trend: Vec<TrendItem<DurationTrendData>>,
+    retry_trend: Vec<TrendItem<RetryTrendData>>,
+    /// Number of attempts retried in this run
+    retries: u32,
+    /// Whether the aggregate files have been written by `on_summary`
+    finalized: bool,
+    sink: Box<dyn ResultSink>,
+    /// Whether `sink` was replaced, so files no longer land in `results_dir`
+    custom_sink: bool,
+    /// Sources of the attachments written so far in this run
+    written_attachments: Mutex<HashSet<String>>,
+    /// Ids of the unnamed tests already warned about, see [`display_test_name`]
+    warned_unnamed: Mutex<HashSet<String>>,
+    /// Tests whose result could not be written
+    write_failures: Vec<String>,
+}
+
+/// Media types whose bodies are binary or streamed and not worth attaching
+const DEFAULT_SKIP_BODY_TYPES: &[&str] = &[
+    "video/",
+    "audio/",
+    "application/octet-stream",
+    "text/event-stream",
+];
+
+/// Decides the status of a test that returned an error or panicked
+type ErrorMapper = Box<dyn Fn(&runner::Error) -> Status + Send>;
+
+/// Invoked once all report files of the run have been written
+type FinishFn = Box<dyn Fn(&runner::TestSummary, &RunSummary) + Send>;
+
+/// Classifies a finished test as a module fixture instead of a regular test
+type FixtureFn = Box<dyn Fn(&Test) -> Option<FixtureKind> + Send>;
+
+/// Returns the Allure severity of a finished test, if it has one
+type SeverityFn = Box<dyn Fn(&Test) -> Option<String> + Send>;
+
+/// Returns the Markdown description of a finished test, if it has one
+type DescriptionFn = Box<dyn Fn(&Test) -> Option<String> + Send>;
+
+/// Returns the issue and TMS links of a finished test
+type LinksFn = Box<dyn Fn(&Test) -> Vec<Link> + Send>;
+
+/// Returns the tags of a finished test, such as `expect-status:404`
+type TagsFn = Box<dyn Fn(&Test) -> Vec<String> + Send>;
+
+/// Generates the uuid of each result and container
+type UuidFn = Box<dyn Fn() -> uuid::Uuid + Send>;
+
+/// Which side of a container a fixture is reported on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureKind {
+    /// Setup that ran before the module's tests, reported in the container's `befores`.
+    Before,
+    /// Teardown that ran after the module's tests, reported in the container's `afters`.
+    After,
+}
+
+/// What a reporter has written so far, see [`AllureReporter::run_summary`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunSummary {
+    /// Test results, one per attempt, including those added with
+    /// [`add_external_result`](AllureReporter::add_external_result).
+    pub results: usize,
+    /// Distinct attachment files; inlined attachments are not counted.
+    pub attachments: usize,
+    /// Containers holding fixtures, which `on_summary` writes.
+    pub containers: usize,
+    /// Attempts tanu retried; each one is also counted in `results`.
+    pub retries: u32,
+    /// Absolute path of `results_dir`.
+    pub results_dir: PathBuf,
+}
+
+impl RunSummary {
+    /// The line [`AllureReporter::print_summary`] prints, without its `tanu-allure:` prefix
+    pub fn to_line(&self) -> String {
+        format!(
+            "wrote {} result(s), {} attachment(s) and {} container(s) to {}",
+            self.results,
+            self.attachments,
+            self.containers,
+            self.results_dir.display()
+        )
+    }
 }
 
+/// How result files are written to `results_dir`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Plain `*-result.json` files that Allure reads directly.
+    #[default]
+    None,
+    /// `*-result.json.zst` files, which must be decompressed before Allure can read them.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// Note written next to compressed results so the directory explains itself
+#[cfg(feature = "zstd")]
+const COMPRESSED_NOTE: &str = "\
+Result files in this directory are compressed with zstd (*-result.json.zst).
+Allure cannot read them as-is; decompress them first, e.g. `zstd -d --rm *-result.json.zst`.
+";
+
+/// Suffixes of the per-test files a run writes into `results_dir`
+const STALE_FILE_SUFFIXES: &[&str] = &[
+    "-result.json",
+    "-result.json.zst",
+    "-container.json",
+    "-attachment",
+    "-attachment.gz",
+    // Left behind by a run killed while writing
+    sink::TEMP_FILE_SUFFIX,
+];
+
 /// Tracks a single test result for history update
 struct RunResult {
     history_id: String,
     status: Status,
     status_details: Option<String>,
+    /// Whether the result was muted, which keeps it out of the history statistic
+    muted: bool,
     start: i64,
     stop: i64,
-    uuid: String,
+    uuid: uuid::Uuid,
+    /// The result's full name, falling back to its name
+    full_name: String,
 }
 
 enum Event {
@@ -91,56 +967,44 @@ enum Event {
     Http(Box<http::Log>),
 }
 
-impl From<&Event> for Step {
-    fn from(event: &Event) -> Self {
-        match event {
-            Event::Check(check) => {
-                let now = system_time_to_unix_millis(std::time::SystemTime::now());
-                Step {
-                    name: strip_ansi_escapes::strip_str(&check.expr),
-                    parameters: Default::default(),
-                    attachments: Default::default(),
-                    status: if check.result {
-                        Status::Passed
-                    } else {
-                        Status::Failed
-                    },
-                    status_details: Default::default(),
-                    stage: Some(Stage::Finished),
-                    start: Some(now),
-                    stop: Some(now),
-                    steps: vec![],
-                }
-            }
-            Event::Http(log) => Step {
-                name: log.request.url.to_string(),
-                parameters: {
-                    let mut parameters = Vec::new();
-                    push_header_parameters(&mut parameters, "request.header", &log.request.headers);
-                    push_header_parameters(
-                        &mut parameters,
-                        "response.header",
-                        &log.response.headers,
-                    );
-                    parameters
-                },
-                attachments: Default::default(),
-                status: to_status(log.response.status),
-                status_details: Default::default(),
-                stage: Some(Stage::Finished),
-                start: Some(system_time_to_unix_millis(log.started_at)),
-                stop: Some(system_time_to_unix_millis(log.ended_at)),
-                steps: vec![],
-            },
-        }
-    }
+/// One HTTP call in the `timeline` attachment
+#[derive(Serialize)]
+struct TimelineEntry {
+    name: String,
+    start: i64,
+    stop: i64,
 }
 
-#[derive(Default)]
 struct Buffer {
     events: Vec<Event>,
+    /// 1-based attempt number, incremented every time tanu retries the test
+    attempt: u32,
+    /// Position of the test in the order tests started in, counted from 1
+    start_order: u64,
+    /// Statuses of the attempts tanu has already retried
+    earlier_statuses: Vec<Status>,
+    /// Whether tanu retried this attempt, which is then not the last one
+    retried: bool,
+}
+
+impl Buffer {
+    fn new(start_order: u64) -> Self {
+        Buffer {
+            events: Vec::new(),
+            attempt: 1,
+            start_order,
+            earlier_statuses: Vec::new(),
+            retried: false,
+        }
+    }
 }
 
+// tanu moves reporters onto its own task, which requires them to be `Send`
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<AllureReporter>();
+};
+
 impl Default for AllureReporter {
     fn default() -> Self {
         AllureReporter::new()
@@ -148,21 +1012,101 @@ impl Default for AllureReporter {
 }
 
 impl AllureReporter {
+    /// Creates a reporter writing into `allure-results` in the current directory.
     pub fn new() -> Self {
         Self::with_results_dir("allure-results")
     }
 
+    /// Creates a reporter writing into `results_dir`.
+    ///
+    /// Options are set by chaining the consuming methods below on the returned reporter, or
+    /// all as `with_*` setters of an [`AllureReporterBuilder`](crate::AllureReporterBuilder),
+    /// which also checks patterns and combinations of options before the run starts.
     pub fn with_results_dir(results_dir: impl Into<String>) -> Self {
         let results_dir = results_dir.into();
-        let history = Self::load_history(&results_dir);
+        let history_dir = Path::new(&results_dir).join("history");
+        let history = Self::load_history(&history_dir);
+        let duration_trend = Self::load_trend(&history_dir, "duration-trend.json");
+        let retry_trend = Self::load_trend(&history_dir, "retry-trend.json");
         let environment = Self::initialize_environment();
 
         AllureReporter {
-            results_dir,
             buffer: IndexMap::new(),
             history,
             current_run_results: Vec::new(),
             environment,
+            header_masking: HeaderMasking::default(),
+            masked_query_params: DEFAULT_MASKED_QUERY_PARAMS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            body_redaction: BodyRedaction::default(),
+            max_body_bytes: None,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            attachment_compression: None,
+            inline_attachments: None,
+            test_case_id_algo: HashAlgo::default(),
+            fixture_fn: None,
+            containers: IndexMap::new(),
+            finish_fn: None,
+            uuid_fn: Box::new(uuid::Uuid::new_v4),
+            print_summary: false,
+            informational_status: Status::Passed,
+            attach_output: false,
+            incremental_history: false,
+            shared_results_dir: false,
+            error_mapper: Box::new(default_error_status),
+            environment_label: false,
+            environment_in_history_id: false,
+            attach_timeline: false,
+            media_type_overrides: HashMap::new(),
+            skip_body_types: DEFAULT_SKIP_BODY_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            compression: Compression::None,
+            coalesce_http_calls: false,
+            tags_fn: None,
+            start_order_parameter: false,
+            next_start_order: 0,
+            stdout_json: false,
+            #[cfg(feature = "json-schema")]
+            response_schemas: Vec::new(),
+            version_label: false,
+            history_id_config: HistoryIdConfig::default(),
+            attach_har: false,
+            project_parameter: true,
+            host_thread_labels: true,
+            header_parameters: true,
+            http_parameters: true,
+            service_labels: false,
+            categories: Vec::new(),
+            severity_fn: None,
+            default_severity: None,
+            clean: false,
+            initialized: false,
+            prepare_error: None,
+            executor: None,
+            step_grouping: false,
+            description_fn: None,
+            links_fn: None,
+            bdd_labels: false,
+            global_labels: Vec::new(),
+            results_index: false,
+            issue_url_template: None,
+            flaky_history_window: None,
+            curl_attachments: false,
+            pretty: true,
+            duration_trend,
+            retry_trend,
+            retries: 0,
+            finalized: false,
+            sink: Box::new(FilesystemSink::new(&results_dir)),
+            custom_sink: false,
+            written_attachments: Mutex::new(HashSet::new()),
+            warned_unnamed: Mutex::new(HashSet::new()),
+            write_failures: Vec::new(),
+            results_dir,
         }
     }
 
@@ -210,6 +1154,15 @@ impl AllureReporter {
         self.environment.extend(env);
     }
 
+    /// Builder form of [`set_environment`](Self::set_environment).
+    ///
+    /// The entries are written to `environment.properties` together with the preset
+    /// values when the first test starts.
+    pub fn with_environment(mut self, env: HashMap<String, String>) -> Self {
+        self.set_environment(env);
+        self
+    }
+
     /// Loads environment variables from system environment with a specific prefix.
     /// Variables with the prefix will be added with the prefix stripped.
     ///
@@ -225,247 +1178,4601 @@ impl AllureReporter {
         Self::load_env_with_prefix(&mut self.environment, prefix);
     }
 
-    /// Loads existing history.json from the history subdirectory
-    fn load_history(results_dir: &str) -> History {
-        let path = Path::new(results_dir).join("history").join("history.json");
-        if !path.exists() {
-            return History::new();
-        }
-        fs::read_to_string(&path)
-            .ok()
-            .and_then(|s| serde_json::from_str(&s).ok())
-            .unwrap_or_default()
+    /// Masks the header with the given name, compared case-insensitively.
+    ///
+    /// Authorization, Proxy-Authorization, Cookie, Set-Cookie, X-API-Key and X-Auth-Token are
+    /// masked by default.
+    pub fn add_masked_header(&mut self, name: &str) {
+        self.header_masking.names.insert(name.to_ascii_lowercase());
     }
 
-    fn ensure_results_dir(&self) -> eyre::Result<()> {
-        let path = Path::new(&self.results_dir);
-        if !path.exists() {
-            fs::create_dir_all(path)?;
-        }
-        Ok(())
+    /// Masks the query parameter with the given name, compared case-insensitively, in the
+    /// `request.query.*` parameters of HTTP steps.
+    ///
+    /// `token`, `access_token`, `api_key`, `apikey`, `key`, `password`, `secret`,
+    /// `client_secret` and `signature` are masked by default. The step name still shows the
+    /// full URL.
+    pub fn add_masked_query_param(&mut self, name: &str) {
+        self.masked_query_params.insert(name.to_ascii_lowercase());
     }
 
-    fn map_to_allure_test_result(
-        &self,
-        project: &str,
-        module: &str,
-        test_name: &str,
-        events: &[Event],
-        test: &Test,
-    ) -> TestResult {
-        let status = to_test_status(test);
-
-        let status_details = if let Err(e) = &test.result {
-            Some(StatusDetails {
-                known: None,
-                muted: None,
-                flaky: None,
-                message: Some(strip_ansi_escapes::strip_str(e.to_string())),
-                trace: None,
-            })
-        } else {
-            None
-        };
+    /// Replaces the masked header names, including the default ones, with `names`.
+    pub fn with_masked_headers<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.header_masking.names = names
+            .into_iter()
+            .map(|name| name.as_ref().to_ascii_lowercase())
+            .collect();
+        self
+    }
 
-        let steps: Vec<_> = events.iter().map(Step::from).collect();
+    /// Masks every header whose name matches the given regular expression.
+    ///
+    /// The pattern must match the whole header name and is compared case-insensitively,
+    /// so `.*-token` masks both `x-api-token` and `X-Auth-Token`. Headers named with
+    /// [`add_masked_header`](Self::add_masked_header) or
+    /// [`with_masked_headers`](Self::with_masked_headers) are masked as well.
+    pub fn add_masked_header_pattern(&mut self, pattern: &str) -> eyre::Result<()> {
+        let regex = RegexBuilder::new(&format!("^(?:{pattern})$"))
+            .case_insensitive(true)
+            .build()?;
+        self.header_masking.patterns.push(regex);
+        Ok(())
+    }
+
+    /// Replaces the value of every JSON object key with the given name, compared
+    /// case-insensitively, with `***` in attached request and response bodies.
+    ///
+    /// Only bodies with a JSON content type are redacted, at any nesting depth; a redacted
+    /// body is attached re-serialized. Nothing is redacted by default.
+    pub fn add_redacted_body_key(&mut self, name: &str) {
+        self.body_redaction.keys.insert(name.to_ascii_lowercase());
+    }
+
+    /// Redacts every JSON object key matching the given regular expression, like
+    /// [`add_redacted_body_key`](Self::add_redacted_body_key).
+    ///
+    /// The pattern must match the whole key and is compared case-insensitively.
+    pub fn add_redacted_body_key_pattern(&mut self, pattern: &str) -> eyre::Result<()> {
+        let regex = RegexBuilder::new(&format!("^(?:{pattern})$"))
+            .case_insensitive(true)
+            .build()?;
+        self.body_redaction.patterns.push(regex);
+        Ok(())
+    }
+
+    /// Validates the JSON body of every response whose URL path matches `path_pattern`
+    /// against `schema`.
+    ///
+    /// The outcome is reported as a `response matches schema` sub-step of the HTTP step,
+    /// failed when the body does not conform, with the violations attached. The pattern must
+    /// match the whole path; when several patterns match, the first one added wins.
+    #[cfg(feature = "json-schema")]
+    pub fn add_response_schema(
+        &mut self,
+        path_pattern: &str,
+        schema: &serde_json::Value,
+    ) -> eyre::Result<()> {
+        let pattern = Regex::new(&format!("^(?:{path_pattern})$"))?;
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|e| eyre::eyre!("invalid JSON Schema for \"{path_pattern}\": {e}"))?;
+        self.response_schemas.push((pattern, validator));
+        Ok(())
+    }
+
+    /// Limits how many bytes of each HTTP body are written into its attachment.
+    ///
+    /// tanu hands the reporter each body in memory, so larger bodies are truncated to the
+    /// limit before they are written rather than streamed.
+    pub fn max_body_bytes(mut self, limit: u64) -> Self {
+        self.max_body_bytes = Some(limit);
+        self
+    }
+
+    /// Gzips textual HTTP bodies of at least `min_bytes` before attaching them.
+    ///
+    /// Compressed attachments are written as `*-attachment.gz` with type `application/gzip`,
+    /// so Allure offers them for download instead of showing them inline. Binary bodies and
+    /// smaller ones are attached as they are. Disabled by default.
+    pub fn with_attachment_compression(mut self, min_bytes: usize) -> Self {
+        self.attachment_compression = Some(min_bytes);
+        self
+    }
+
+    /// Embeds attachments of at most `max_bytes` into the result as base64 `data:` URIs in
+    /// their `source` instead of writing separate files.
+    ///
+    /// Meant for upload pipelines that want one self-contained file per result; the Allure
+    /// generator itself only reads attachment files, so it will not show inlined attachments.
+    /// Larger attachments are still written as files. Disabled by default.
+    pub fn with_inline_attachments(mut self, max_bytes: usize) -> Self {
+        self.inline_attachments = Some(max_bytes);
+        self
+    }
+
+    /// Limits how many bytes of each header value are shown in step parameters.
+    ///
+    /// Longer values, such as large `Content-Security-Policy` headers, are cut off and end
+    /// with `…(truncated)`. Defaults to 1024; pass `usize::MAX` to keep values whole.
+    pub fn max_header_bytes(mut self, limit: usize) -> Self {
+        self.max_header_bytes = limit;
+        self
+    }
+
+    /// Selects the hash algorithm used to derive `testCaseId` from the test's identity.
+    ///
+    /// Defaults to [`HashAlgo::Md5`] of the full name, `project::module#test`, which matches
+    /// the ids of Allure's own integrations, so a test case keeps its id when a suite moves
+    /// to tanu. Changing the algorithm gives every test a new id and a new test case in
+    /// TestOps. Select [`HashAlgo::Sha256`] to hash the identity the history_id is derived
+    /// from instead.
+    pub fn test_case_id_algo(mut self, algo: HashAlgo) -> Self {
+        self.test_case_id_algo = algo;
+        self
+    }
+
+    /// Routes tests classified by `fixture_fn` into their module's container.
+    ///
+    /// A test for which the callback returns a [`FixtureKind`] is not written as a test result;
+    /// its checks and HTTP calls become a `befores`/`afters` entry of a `{uuid}-container.json`
+    /// wrapping the other tests of the same project and module, so setup failures show up next
+    /// to the tests they affected.
+    pub fn with_fixture_fn(
+        mut self,
+        fixture_fn: impl Fn(&Test) -> Option<FixtureKind> + Send + 'static,
+    ) -> Self {
+        self.fixture_fn = Some(Box::new(fixture_fn));
+        self
+    }
+
+    /// Registers a callback that runs after the run's results, containers, history and
+    /// environment files have all been written.
+    ///
+    /// The callback receives tanu's run summary and what the reporter wrote, including the
+    /// absolute results directory, which makes it the place to trigger uploads or
+    /// notifications. It runs once per reporter.
+    pub fn on_finish(
+        mut self,
+        callback: impl Fn(&runner::TestSummary, &RunSummary) + Send + 'static,
+    ) -> Self {
+        self.finish_fn = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets the step status used for 1xx informational responses such as
+    /// `100 Continue` or `101 Switching Protocols`. Defaults to [`Status::Passed`].
+    pub fn informational_status(mut self, status: Status) -> Self {
+        self.informational_status = status;
+        self
+    }
+
+    /// Attaches the output each test printed through [`output::stdout`]/[`output::stderr`]
+    /// (or the `allure_println!`/`allure_eprintln!` macros) inside an [`output::capture`]
+    /// scope as `text/plain` attachments.
+    pub fn attach_output(mut self, enabled: bool) -> Self {
+        self.attach_output = enabled;
+        self
+    }
+
+    /// Merges each result into `history/history.json` as soon as its test finishes instead
+    /// of once at the end of the run.
+    ///
+    /// A run that crashes midway still contributes the tests that completed. Every update
+    /// re-reads and rewrites the file under a lock, so this costs more I/O for large suites.
+    ///
+    /// The file is read and written in `results_dir` directly, so this cannot be combined
+    /// with [`with_sink`](Self::with_sink); the run then fails in `on_summary` without
+    /// writing anything.
+    pub fn incremental_history(mut self, enabled: bool) -> Self {
+        self.incremental_history = enabled;
+        self
+    }
+
+    /// Declares that other processes, e.g. the other test binaries of a workspace, write
+    /// into the same `results_dir` at the same time.
+    ///
+    /// The history, the trends and `results-index.json` written at the end of the run are
+    /// then merged into the files found on disk, under the same file lock as
+    /// [`incremental_history`](Self::incremental_history), so no binary drops the results of
+    /// another. Each binary adds its own data point to the trends. Result files are named by
+    /// uuid and never collide.
+    ///
+    /// Cannot be combined with [`with_clean`](Self::with_clean), which would delete the
+    /// results the other binaries have already written, nor with
+    /// [`with_sink`](Self::with_sink), as the merge needs the files on disk; the run then
+    /// fails in `on_summary` without writing anything.
+    pub fn shared_results_dir(mut self, enabled: bool) -> Self {
+        self.shared_results_dir = enabled;
+        self
+    }
+
+    /// Replaces the mapping from a failed test's [`runner::Error`] to its status.
+    ///
+    /// The default, [`default_error_status`], maps returned errors to `failed` and panics
+    /// (as well as any error kind it does not know) to `broken`.
+    pub fn with_error_mapper(
+        mut self,
+        mapper: impl Fn(&runner::Error) -> Status + Send + 'static,
+    ) -> Self {
+        self.error_mapper = Box::new(mapper);
+        self
+    }
+
+    /// Adds an `environment` label and an `Environment` parameter naming the API under test.
+    ///
+    /// The value is the project's `base_url` from tanu.toml, or the origin of the test's first
+    /// HTTP call when the project has none.
+    pub fn environment_label(mut self, enabled: bool) -> Self {
+        self.environment_label = enabled;
+        self
+    }
+
+    /// Includes the `Environment` parameter in the history_id, so runs against different
+    /// environments get separate histories. Requires [`Self::environment_label`].
+    pub fn environment_in_history_id(mut self, enabled: bool) -> Self {
+        self.environment_in_history_id = enabled;
+        self
+    }
+
+    /// Attaches a `timeline` JSON array of `{name, start, stop}` for the test's HTTP calls,
+    /// which tooling can render as a waterfall of the requests.
+    pub fn attach_timeline(mut self, enabled: bool) -> Self {
+        self.attach_timeline = enabled;
+        self
+    }
+
+    /// Sets the `Content-Type`s whose request and response bodies are not attached.
+    ///
+    /// Entries ending in `/` match every subtype (`video/` matches `video/mp4`). A skipped
+    /// body is recorded as a `request.body` or `response.body` parameter with the value
+    /// `<skipped: ...>` instead. Defaults to `video/`, `audio/`, `application/octet-stream`
+    /// and `text/event-stream`.
+    pub fn skip_body_types(mut self, types: Vec<String>) -> Self {
+        self.skip_body_types = types;
+        self
+    }
+
+    /// Attaches bodies declared with one media type as another, e.g. a vendor type such
+    /// as `application/vnd.api+json` as `application/json` so Allure pretty-prints it.
+    ///
+    /// Keys are matched case-insensitively against the `Content-Type` without parameters;
+    /// a body without one is looked up as `application/octet-stream`. Bodies of that type
+    /// that are not overridden are sniffed instead: content that parses as JSON, or starts
+    /// like XML or HTML, is attached as such (and is not skipped by
+    /// [`skip_body_types`](Self::skip_body_types)).
+    pub fn with_media_type_overrides(mut self, overrides: HashMap<String, String>) -> Self {
+        self.media_type_overrides = overrides
+            .into_iter()
+            .map(|(from, to)| (from.to_ascii_lowercase(), to))
+            .collect();
+        self
+    }
+
+    /// Sets how result files are written.
+    ///
+    /// [`Compression::Zstd`] (behind the `zstd` feature) writes `*-result.json.zst` plus a
+    /// `COMPRESSED.txt` note, which saves space in archival storage but means the directory
+    /// has to be decompressed before `allure generate` can read it. Attachments, containers
+    /// and history stay uncompressed.
+    pub fn compress(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Merges consecutive HTTP calls with the same method, path and status into one step.
+    ///
+    /// Useful for polling loops: the merged step spans from the first call's start to the
+    /// last call's end, keeps the parameters of the first call and the body attachments and
+    /// schema sub-steps of every call. Unless [`http_parameters`](Self::http_parameters) is
+    /// off, it also carries a `count` parameter. A call with a different status starts a new
+    /// step. Disabled by default.
+    pub fn coalesce_http_calls(mut self, enabled: bool) -> Self {
+        self.coalesce_http_calls = enabled;
+        self
+    }
+
+    /// Sets a closure returning the tags of each test.
+    ///
+    /// tanu has no notion of tags, so they are derived from the test, e.g. from its name.
+    /// Tags become labels as described in [`Label::from_tag`]: `severity:critical`,
+    /// `owner:alice`, `epic:`, `feature:` and `story:` set the matching label, other
+    /// `name:value` tags a custom label and plain tags a `tag` label.
+    ///
+    /// The following tags change how the test is reported instead of becoming labels:
+    ///
+    /// - `expect-status:<code>`: HTTP calls answered with `<code>` are reported as passed
+    ///   with the note `expected <code>`, for negative tests. May be given more than once.
+    /// - `muted`: the result is marked muted, which Allure leaves out of its statistics.
+    /// - `known_issue:<issue>`: the result is marked as a known issue and linked to
+    ///   `<issue>`, a URL or an id filled into
+    ///   [`with_issue_url_template`](Self::with_issue_url_template).
+    pub fn with_tags_fn<F>(mut self, tags_fn: F) -> Self
+    where
+        F: Fn(&Test) -> Vec<String> + Send + 'static,
+    {
+        self.tags_fn = Some(Box::new(tags_fn));
+        self
+    }
+
+    /// Adds a `start_order` parameter numbering tests in the order they started, from 1.
+    ///
+    /// Helps diagnosing order-dependent flakiness. The parameter is excluded from the
+    /// history id. Disabled by default.
+    pub fn start_order_parameter(mut self, enabled: bool) -> Self {
+        self.start_order_parameter = enabled;
+        self
+    }
+
+    /// Also prints every finished result to stdout as a single line of JSON.
+    ///
+    /// Each line starts with [`STDOUT_JSON_MARKER`] so that a log shipper can pick the
+    /// results out of the rest of the output. Result files are still written. Disabled by
+    /// default.
+    pub fn stdout_json(mut self, enabled: bool) -> Self {
+        self.stdout_json = enabled;
+        self
+    }
+
+    /// Adds a `tanu_allure_version` label with the version of this crate to every result.
+    ///
+    /// Helps telling apart reports produced by different versions when their format
+    /// differs. Disabled by default.
+    pub fn version_label(mut self, enabled: bool) -> Self {
+        self.version_label = enabled;
+        self
+    }
+
+    /// Chooses which parts of a test's identity its history_id is computed from.
+    ///
+    /// Changing this starts a new history for every test, as their ids change.
+    pub fn history_id_components(mut self, config: HistoryIdConfig) -> Self {
+        self.history_id_config = config;
+        self
+    }
+
+    /// Attaches a `requests.har` file with every HTTP call of the test in HAR 1.2 format.
+    ///
+    /// The file opens in browser devtools and HTTP debuggers such as Charles or Proxyman.
+    /// Sensitive headers are masked as in step parameters and response bodies are cut to
+    /// [`max_body_bytes`](Self::max_body_bytes); request bodies are kept whole so that the
+    /// calls can be replayed. tanu does not record the HTTP version, which is therefore
+    /// `unknown`. Disabled by default.
+    pub fn attach_har(mut self, enabled: bool) -> Self {
+        self.attach_har = enabled;
+        self
+    }
+
+    /// Adds the excluded `Project` parameter to every result. Enabled by default.
+    pub fn project_parameter(mut self, enabled: bool) -> Self {
+        self.project_parameter = enabled;
+        self
+    }
+
+    /// Adds `host` and `thread` labels to every result, which Allure's timeline groups tests
+    /// by. The host falls back to `localhost` when the hostname cannot be determined, and the
+    /// thread is the tanu worker that ran the test. Enabled by default.
+    pub fn host_thread_labels(mut self, enabled: bool) -> Self {
+        self.host_thread_labels = enabled;
+        self
+    }
+
+    /// Adds the request and response headers of HTTP steps as parameters. Enabled by default.
+    pub fn header_parameters(mut self, enabled: bool) -> Self {
+        self.header_parameters = enabled;
+        self
+    }
+
+    /// Adds the method, response status, body sizes (`request.bytes`, `response.bytes`) and
+    /// query string (`request.query.*`) of HTTP steps as parameters. Enabled by default.
+    pub fn http_parameters(mut self, enabled: bool) -> Self {
+        self.http_parameters = enabled;
+        self
+    }
+
+    /// Adds a `service` label for every distinct host a test sent HTTP calls to.
+    ///
+    /// Lets the report be filtered by target service in suites that call several of them.
+    /// Disabled by default.
+    pub fn service_labels(mut self, enabled: bool) -> Self {
+        self.service_labels = enabled;
+        self
+    }
+
+    /// Turns off everything beyond status, timing, suite labels and steps. HTTP steps keep
+    /// their body attachments and, for skipped bodies, the `request.body`/`response.body`
+    /// placeholder parameters.
+    ///
+    /// Shorthand for disabling [`project_parameter`](Self::project_parameter),
+    /// [`host_thread_labels`](Self::host_thread_labels),
+    /// [`header_parameters`](Self::header_parameters),
+    /// [`http_parameters`](Self::http_parameters),
+    /// [`coalesce_http_calls`](Self::coalesce_http_calls),
+    /// [`start_order_parameter`](Self::start_order_parameter),
+    /// [`environment_label`](Self::environment_label),
+    /// [`service_labels`](Self::service_labels) and
+    /// [`version_label`](Self::version_label). Options set afterwards still apply.
+    pub fn minimal(self) -> Self {
+        self.project_parameter(false)
+            .host_thread_labels(false)
+            .header_parameters(false)
+            .http_parameters(false)
+            .coalesce_http_calls(false)
+            .start_order_parameter(false)
+            .environment_label(false)
+            .service_labels(false)
+            .version_label(false)
+    }
+
+    /// Writes a result that did not come from tanu, e.g. one converted from another test
+    /// framework, into `results_dir`.
+    ///
+    /// The result goes through the same validation, output options and history update as
+    /// the results of tanu tests. Its `history_id` must be set.
+    pub fn add_external_result(&mut self, result: TestResult) -> eyre::Result<()> {
+        if let Some(error) = &self.prepare_error {
+            eyre::bail!("{error}");
+        }
+        let run_result = self.write_result(&result)?;
+        if self.incremental_history {
+            self.append_history(std::slice::from_ref(&run_result))?;
+        }
+        self.current_run_results.push(run_result);
+        Ok(())
+    }
+
+    /// Writes the given categories to `categories.json`, replacing any set before.
+    ///
+    /// Without categories no `categories.json` is written and Allure applies its built-in
+    /// ones.
+    pub fn with_categories(mut self, categories: Vec<Category>) -> Self {
+        self.categories = categories;
+        self
+    }
+
+    /// Writes [`Category::defaults`] to `categories.json`.
+    pub fn with_default_categories(self) -> Self {
+        self.with_categories(Category::defaults())
+    }
+
+    /// Continues the `history.json` and trend files found in the history directory `path`
+    /// instead of the ones in `results_dir/history`.
+    ///
+    /// Use this when the previous run's history lives elsewhere, e.g. the `history`
+    /// directory of a downloaded `allure-report`. The updated history is still written to
+    /// `results_dir`. A missing or malformed file starts a new history.
+    pub fn with_history_dir(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        self.history = Self::load_history(path);
+        self.duration_trend = Self::load_trend(path, "duration-trend.json");
+        self.retry_trend = Self::load_trend(path, "retry-trend.json");
+        self
+    }
+
+    /// Sets a closure returning the severity of each test.
+    ///
+    /// Allure knows `blocker`, `critical`, `normal`, `minor` and `trivial`; any other value is
+    /// reported as `normal` with a warning. A severity returned here replaces one set with a
+    /// `severity:` tag, which in turn replaces the
+    /// [default severity](Self::with_default_severity). A result gets at most one severity;
+    /// of several `severity:` tags the first wins.
+    pub fn with_severity_fn<F>(mut self, severity_fn: F) -> Self
+    where
+        F: Fn(&Test) -> Option<String> + Send + 'static,
+    {
+        self.severity_fn = Some(Box::new(severity_fn));
+        self
+    }
+
+    /// Sets the severity of tests that neither [`with_severity_fn`](Self::with_severity_fn)
+    /// nor a `severity:` tag gives one.
+    ///
+    /// Without a default such tests get no severity label, which Allure shows as `normal`.
+    pub fn with_default_severity(mut self, severity: impl Into<String>) -> Self {
+        self.default_severity = Some(severity.into());
+        self
+    }
+
+    /// Removes result, container and attachment files left in `results_dir` by earlier runs
+    /// when the first test starts.
+    ///
+    /// Only files written by this reporter (`*-result.json`, `*-result.json.zst`,
+    /// `*-container.json`, `*-attachment` and `*-attachment.gz`) are deleted; `history/` and
+    /// anything else in the directory are kept. Disabled by default, and not allowed with
+    /// [`shared_results_dir`](Self::shared_results_dir).
+    pub fn with_clean(mut self, enabled: bool) -> Self {
+        self.clean = enabled;
+        self
+    }
+
+    /// Writes `executor.json` describing the CI build, e.g. from [`Executor::from_env`].
+    ///
+    /// Without an executor no `executor.json` is written.
+    pub fn with_executor(mut self, executor: Executor) -> Self {
+        self.executor = Some(executor);
+        self
+    }
+
+    /// Groups consecutive HTTP calls to the same method and host, together with the checks
+    /// that follow them, under a `METHOD host` parent step.
+    ///
+    /// The parent spans its children and fails when one of them fails. Disabled by default.
+    pub fn with_step_grouping(mut self, enabled: bool) -> Self {
+        self.step_grouping = enabled;
+        self
+    }
+
+    /// Sets a closure returning the Markdown description of each test.
+    ///
+    /// With the `markdown` feature the description is also rendered to `descriptionHtml`,
+    /// with raw HTML escaped.
+    pub fn with_description_fn<F>(mut self, description_fn: F) -> Self
+    where
+        F: Fn(&Test) -> Option<String> + Send + 'static,
+    {
+        self.description_fn = Some(Box::new(description_fn));
+        self
+    }
+
+    /// Sets a closure returning the links of each test, e.g. built with [`Link::issue`] and
+    /// [`Link::tms`].
+    pub fn with_links_fn<F>(mut self, links_fn: F) -> Self
+    where
+        F: Fn(&Test) -> Vec<Link> + Send + 'static,
+    {
+        self.links_fn = Some(Box::new(links_fn));
+        self
+    }
+
+    /// Derives `epic`, `feature` and `story` labels from `epic_`, `feature_` and `story_`
+    /// prefixed segments of the module path.
+    ///
+    /// For `api::epic_checkout::feature_cart::story_add_item` the result gets the epic
+    /// `checkout`, the feature `cart`, the story `add_item` and the suite `api`. Disabled by
+    /// default, in which case the whole module path is the suite.
+    pub fn bdd_labels(mut self, enabled: bool) -> Self {
+        self.bdd_labels = enabled;
+        self
+    }
+
+    /// Chooses between indented and compact JSON for all files the reporter writes.
+    ///
+    /// Compact output is roughly half the size, which adds up when thousands of result files
+    /// are uploaded as CI artifacts. Defaults to indented.
+    pub fn with_pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Hands the produced files to `sink` instead of writing them into `results_dir`.
+    ///
+    /// `results_dir` is still where the previous history is read from and where
+    /// [`with_clean`](Self::with_clean) deletes stale files.
+    /// [`incremental_history`](Self::incremental_history) and
+    /// [`shared_results_dir`](Self::shared_results_dir) merge files on disk under a lock
+    /// and are rejected with a custom sink.
+    pub fn with_sink(mut self, sink: impl ResultSink + 'static) -> Self {
+        self.sink = Box::new(sink);
+        self.custom_sink = true;
+        self
+    }
+
+    /// Adds `labels` to every result, e.g. `Label::custom("team", "payments")`.
+    ///
+    /// They follow the parent suite and suite labels; a label already present on a result
+    /// is not repeated.
+    pub fn with_global_labels(mut self, labels: Vec<Label>) -> Self {
+        self.global_labels = labels;
+        self
+    }
+
+    /// Writes `results-index.json`, mapping the uuid of every result written in the run to
+    /// the test's full name, e.g. `project::module#test`.
+    ///
+    /// Allure ignores the file; it only tells which `{uuid}-result.json` belongs to which test
+    /// when inspecting the directory by hand. Disabled by default.
+    pub fn results_index(mut self, enabled: bool) -> Self {
+        self.results_index = enabled;
+        self
+    }
+
+    /// Sets the URL that `known_issue:<id>` tags link to, with `{}` standing for the id, e.g.
+    /// `https://jira.example.com/browse/{}`.
+    ///
+    /// Without a template only issues given as a full URL are linked.
+    pub fn with_issue_url_template(mut self, template: impl Into<String>) -> Self {
+        self.issue_url_template = Some(template.into());
+        self
+    }
+
+    /// Marks a result flaky when its test both passed and failed or broke within its last
+    /// `runs` runs in the previous history.
+    ///
+    /// This surfaces tests that alternate between runs, on top of those retried within
+    /// one. Disabled by default.
+    pub fn flaky_history_window(mut self, runs: usize) -> Self {
+        self.flaky_history_window = Some(runs);
+        self
+    }
+
+    /// Attaches a `curl` command repeating each HTTP request to its step.
+    ///
+    /// The command carries the method, URL, headers and body; sensitive headers are masked
+    /// as in the step parameters and the body is [redacted](Self::add_redacted_body_key) as
+    /// in its attachment. Disabled by default.
+    pub fn with_curl_attachments(mut self, enabled: bool) -> Self {
+        self.curl_attachments = enabled;
+        self
+    }
+
+    /// Replaces how the uuids of results and containers, and so their file names, are
+    /// generated.
+    ///
+    /// Defaults to random v4 uuids. Handing out a fixed sequence, e.g. from an atomic
+    /// counter with [`uuid::Uuid::from_u128`], makes the output reproducible for golden-file
+    /// comparisons.
+    pub fn with_uuid_fn(mut self, uuid_fn: impl Fn() -> uuid::Uuid + Send + 'static) -> Self {
+        self.uuid_fn = Box::new(uuid_fn);
+        self
+    }
+
+    /// Prints what the run wrote to stderr once it finishes, e.g.
+    /// `tanu-allure: wrote 12 result(s), 30 attachment(s) and 0 container(s) to /ci/allure-results`.
+    ///
+    /// Off by default, as the line would land in the middle of tanu's TUI.
+    pub fn print_summary(mut self, enabled: bool) -> Self {
+        self.print_summary = enabled;
+        self
+    }
+
+    /// Counts the files written so far.
+    pub fn run_summary(&self) -> RunSummary {
+        let results_dir = Path::new(&self.results_dir);
+        RunSummary {
+            results: self.current_run_results.len(),
+            attachments: self
+                .written_attachments
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .len(),
+            containers: self
+                .containers
+                .values()
+                .filter(|container| !container.befores.is_empty() || !container.afters.is_empty())
+                .count(),
+            retries: self.retries,
+            results_dir: std::path::absolute(results_dir)
+                .unwrap_or_else(|_| results_dir.to_path_buf()),
+        }
+    }
+
+    /// Loads an existing history.json from the history directory
+    fn load_history(history_dir: &Path) -> History {
+        let path = history_dir.join("history.json");
+        if !path.exists() {
+            return History::new();
+        }
+        let parsed = fs::read_to_string(&path)
+            .map_err(eyre::Report::from)
+            .and_then(|s| serde_json::from_str(&s).map_err(eyre::Report::from));
+        parsed.unwrap_or_else(|e| {
+            eprintln!(
+                "tanu-allure: ignoring unreadable {}, starting a new history: {e}",
+                path.display()
+            );
+            History::new()
+        })
+    }
+
+    /// Loads an existing trend file from the history directory
+    fn load_trend<T: DeserializeOwned>(history_dir: &Path, file_name: &str) -> Vec<TrendItem<T>> {
+        let path = history_dir.join(file_name);
+        if !path.exists() {
+            return Vec::new();
+        }
+        let parsed = fs::read_to_string(&path)
+            .map_err(eyre::Report::from)
+            .and_then(|s| serde_json::from_str(&s).map_err(eyre::Report::from));
+        parsed.unwrap_or_else(|e| {
+            eprintln!(
+                "tanu-allure: ignoring unreadable {}, starting a new trend: {e}",
+                path.display()
+            );
+            Vec::new()
+        })
+    }
+
+    /// Writes an HTTP body into a new attachment, honoring `max_body_bytes`
+    fn write_body_attachment(
+        &self,
+        name: &str,
+        media_type: &str,
+        body: &[u8],
+    ) -> eyre::Result<Attachment> {
+        let limit = self.max_body_bytes.unwrap_or(u64::MAX);
+        let len = usize::try_from(limit).map_or(body.len(), |limit| limit.min(body.len()));
+        let body = &body[..len];
+
+        match self.attachment_compression {
+            Some(min_bytes) if body.len() >= min_bytes && is_textual(media_type) => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                let compressed = encoder.finish()?;
+                self.store_attachment(name, "application/gzip", ".gz", &compressed)
+            }
+            _ => self.write_attachment(name, media_type, body),
+        }
+    }
+
+    /// Writes `content` as an attachment named after its SHA-256, so identical content is
+    /// stored once however many steps and tests refer to it
+    fn write_attachment(
+        &self,
+        name: &str,
+        media_type: &str,
+        content: &[u8],
+    ) -> eyre::Result<Attachment> {
+        self.store_attachment(name, media_type, "", content)
+    }
+
+    /// Like [`write_attachment`](Self::write_attachment), appending `extension` to the source
+    fn store_attachment(
+        &self,
+        name: &str,
+        media_type: &str,
+        extension: &str,
+        content: &[u8],
+    ) -> eyre::Result<Attachment> {
+        if self
+            .inline_attachments
+            .is_some_and(|max_bytes| content.len() <= max_bytes)
+        {
+            return Ok(Attachment {
+                name: name.to_string(),
+                source: format!(
+                    "data:{media_type};base64,{}",
+                    BASE64_STANDARD.encode(content)
+                ),
+                r#type: media_type.to_string(),
+            });
+        }
+
+        let source = format!("{:x}-attachment{extension}", Sha256::digest(content));
+        let mut written = self
+            .written_attachments
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if !written.contains(&source) {
+            self.sink.write_attachment(&source, content)?;
+            written.insert(source.clone());
+        }
+
+        Ok(Attachment {
+            name: name.to_string(),
+            source,
+            r#type: media_type.to_string(),
+        })
+    }
+
+    fn to_step(&self, event: &Event, expected_statuses: &[u16]) -> eyre::Result<Step> {
+        let step = match event {
+            Event::Check(check) => {
+                let now = unix_millis(std::time::SystemTime::now());
+                let expr = strip_ansi_escapes::strip_str(&check.expr);
+                let (headline, detail) = split_check_expr(&expr);
+
+                // check_eq!/check_ne! render the compared values below the headline;
+                // surface them for failed checks instead of cramming them into the step name.
+                // For check_eq! that rendering is pretty_assertions' line diff of the two sides.
+                let mut attachments = Vec::new();
+                if !check.result && !detail.is_empty() {
+                    let name = if headline.contains("`(left == right)`") {
+                        "assertion diff"
+                    } else {
+                        "captured values"
+                    };
+                    attachments.push(self.write_attachment(
+                        name,
+                        "text/plain",
+                        detail.as_bytes(),
+                    )?);
+                }
+
+                Step {
+                    name: headline.to_string(),
+                    parameters: Default::default(),
+                    attachments,
+                    status: if check.result {
+                        Status::Passed
+                    } else {
+                        Status::Failed
+                    },
+                    // tanu checks carry no message of their own; the expression and the
+                    // rendered values explain the failure
+                    status_details: (!check.result).then(|| StatusDetails {
+                        known: None,
+                        muted: None,
+                        flaky: None,
+                        message: Some(headline.to_string()),
+                        trace: (!detail.is_empty()).then(|| detail.to_string()),
+                    }),
+                    stage: Some(Stage::Finished),
+                    start: Some(now),
+                    stop: Some(now),
+                    steps: vec![],
+                }
+            }
+            Event::Http(log) => self.http_step(log, expected_statuses)?,
+        };
+        Ok(step)
+    }
+
+    fn http_step(&self, log: &http::Log, expected_statuses: &[u16]) -> eyre::Result<Step> {
+        let mut parameters = Vec::new();
+        if self.http_parameters {
+            push_http_parameters(&mut parameters, log, &self.masked_query_params);
+        }
+        if self.header_parameters {
+            push_header_parameters(
+                &mut parameters,
+                "request.header",
+                &log.request.headers,
+                &self.header_masking,
+                self.max_header_bytes,
+            );
+            push_header_parameters(
+                &mut parameters,
+                "response.header",
+                &log.response.headers,
+                &self.header_masking,
+                self.max_header_bytes,
+            );
+        }
+
+        let mut attachments = Vec::new();
+        for (kind, headers, body) in [
+            (
+                "request",
+                &log.request.headers,
+                log.request.body.as_deref().unwrap_or_default(),
+            ),
+            (
+                "response",
+                &log.response.headers,
+                log.response.body.as_str(),
+            ),
+        ] {
+            if body.is_empty() {
+                continue;
+            }
+            let body = self.redact_body(headers, body);
+            let media_type = self.body_media_type(content_type(headers), &body);
+            match media_type.filter(|media_type| self.is_skipped_body_type(media_type)) {
+                Some(skipped) => parameters.push(Parameter {
+                    name: format!("{kind}.body"),
+                    value: format!("<skipped: {skipped}>"),
+                    excluded: None,
+                    mode: None,
+                }),
+                None => attachments.push(self.write_body_attachment(
+                    &format!("{kind} body"),
+                    media_type.unwrap_or("application/octet-stream"),
+                    body.as_bytes(),
+                )?),
+            }
+        }
+
+        if self.curl_attachments {
+            let body = log
+                .request
+                .body
+                .as_deref()
+                .map(|body| self.redact_body(&log.request.headers, body));
+            let command = curl_command(&log.request, body.as_deref(), &self.header_masking);
+            attachments.push(self.write_attachment("curl", "text/plain", command.as_bytes())?);
+        }
+
+        // Negative tests declare the error status they provoke, which is then a success
+        let code = log.response.status.as_u16();
+        let (status, status_details) = if expected_statuses.contains(&code) {
+            let details = StatusDetails {
+                known: None,
+                muted: None,
+                flaky: None,
+                message: Some(format!("expected {code}")),
+                trace: None,
+            };
+            (Status::Passed, Some(details))
+        } else {
+            (
+                http_status(log.response.status, &self.informational_status),
+                None,
+            )
+        };
+        let (start, stop) = time_span(log.started_at, log.ended_at);
+
+        Ok(Step {
+            name: log.request.url.to_string(),
+            parameters,
+            attachments,
+            status,
+            status_details,
+            stage: Some(Stage::Finished),
+            start: Some(start),
+            stop: Some(stop),
+            #[cfg(feature = "json-schema")]
+            steps: self.schema_step(log)?.into_iter().collect(),
+            #[cfg(not(feature = "json-schema"))]
+            steps: vec![],
+        })
+    }
+
+    /// Validates the response body against the first schema registered for the URL path
+    /// and reports the outcome as a sub-step
+    #[cfg(feature = "json-schema")]
+    fn schema_step(&self, log: &http::Log) -> eyre::Result<Option<Step>> {
+        let path = log.request.url.path();
+        let Some((_, validator)) = self
+            .response_schemas
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(path))
+        else {
+            return Ok(None);
+        };
+
+        let errors: Vec<String> = match serde_json::from_str(&log.response.body) {
+            Ok(body) => validator
+                .iter_errors(&body)
+                .map(|error| format!("{}: {error}", error.instance_path()))
+                .collect(),
+            Err(e) => vec![format!("response body is not JSON: {e}")],
+        };
+        let (status, outcome) = if errors.is_empty() {
+            (
+                Status::Passed,
+                "response body conforms to the schema\n".to_string(),
+            )
+        } else {
+            (Status::Failed, errors.join("\n") + "\n")
+        };
+        let attachment =
+            self.write_attachment("schema validation", "text/plain", outcome.as_bytes())?;
+
+        let (_, stop) = time_span(log.started_at, log.ended_at);
+        Ok(Some(Step {
+            name: "response matches schema".to_string(),
+            parameters: vec![],
+            attachments: vec![attachment],
+            status,
+            status_details: Default::default(),
+            stage: Some(Stage::Finished),
+            start: Some(stop),
+            stop: Some(stop),
+            steps: vec![],
+        }))
+    }
+
+    /// Whether bodies of this media type are left out of the report.
+    /// Entries ending in `/` match a whole top-level type such as `video/`.
+    fn is_skipped_body_type(&self, media_type: &str) -> bool {
+        self.skip_body_types.iter().any(|skipped| {
+            if skipped.ends_with('/') {
+                media_type
+                    .get(..skipped.len())
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case(skipped))
+            } else {
+                media_type.eq_ignore_ascii_case(skipped)
+            }
+        })
+    }
+
+    /// Returns the buffer of a test, creating it and assigning its start order the first
+    /// time the test is seen
+    fn buffer_mut(&mut self, project: &str, module: &str, test_name: &str) -> &mut Buffer {
+        let key = (
+            project.to_string(),
+            module.to_string(),
+            test_name.to_string(),
+        );
+        let next_start_order = &mut self.next_start_order;
+        self.buffer.entry(key).or_insert_with(|| {
+            *next_start_order += 1;
+            Buffer::new(*next_start_order)
+        })
+    }
+
+    /// Resolves the media type a body is attached as: an override of its declared type,
+    /// else the sniffed type of an untyped or `application/octet-stream` body, else the
+    /// declared type
+    fn body_media_type<'a>(&'a self, declared: Option<&'a str>, body: &str) -> Option<&'a str> {
+        let key = declared
+            .unwrap_or("application/octet-stream")
+            .to_ascii_lowercase();
+        if let Some(media_type) = self.media_type_overrides.get(&key) {
+            return Some(media_type);
+        }
+        if key == "application/octet-stream" {
+            if let Some(sniffed) = sniff_media_type(body) {
+                return Some(sniffed);
+            }
+        }
+        declared
+    }
+
+    /// Links a `known_issue:` tag's value, either a URL itself or an id filled into the
+    /// issue URL template
+    fn issue_link(&self, issue: &str) -> Option<Link> {
+        if let Ok(url) = url::Url::parse(issue) {
+            return Some(Link::issue(issue, url));
+        }
+        let template = self.issue_url_template.as_ref()?;
+        match url::Url::parse(&template.replace("{}", issue)) {
+            Ok(url) => Some(Link::issue(issue, url)),
+            Err(e) => {
+                eprintln!("tanu-allure: cannot link known issue \"{issue}\": {e}");
+                None
+            }
+        }
+    }
+
+    /// Whether the recent runs of `history_id` in the previous history both passed and
+    /// failed, see [`flaky_history_window`](Self::flaky_history_window)
+    fn is_flaky_in_history(&self, history_id: &str) -> bool {
+        let Some(window) = self.flaky_history_window else {
+            return false;
+        };
+        let Some(entry) = self.history.get(history_id) else {
+            return false;
+        };
+        let recent = &entry.items[..window.min(entry.items.len())];
+        let passed = recent.iter().any(|item| item.status == Status::Passed);
+        let failed = recent
+            .iter()
+            .any(|item| matches!(item.status, Status::Failed | Status::Broken));
+        passed && failed
+    }
+
+    fn tags(&self, test: &Test) -> Vec<String> {
+        self.tags_fn
+            .as_ref()
+            .map(|tags_fn| tags_fn(test))
+            .unwrap_or_default()
+    }
+
+    fn to_test_status(&self, test: &Test) -> Status {
+        match &test.result {
+            Ok(_) => Status::Passed,
+            Err(e) => (self.error_mapper)(e),
+        }
+    }
+
+    /// Builds one step per event. `expected_statuses` are the HTTP statuses the test declared
+    /// with `expect-status:<code>` tags.
+    fn to_steps(&self, events: &[Event], expected_statuses: &[u16]) -> eyre::Result<Vec<Step>> {
+        // Every step with the `METHOD host` of its event, `None` for checks
+        let mut steps: Vec<(Option<String>, Step)> = Vec::new();
+        // The method, path and status of the last step if it is an HTTP call, with how many
+        // calls it already stands for
+        let mut last_call: Option<(&http::Method, &str, http::StatusCode, usize)> = None;
+        for event in events {
+            if let (true, Event::Http(log)) = (self.coalesce_http_calls, event) {
+                let key = (
+                    &log.request.method,
+                    log.request.url.path(),
+                    log.response.status,
+                );
+                if let (Some((method, path, status, count)), Some((_, step))) =
+                    (&mut last_call, steps.last_mut())
+                {
+                    if (*method, *path, *status) == key {
+                        *count += 1;
+                        // The bodies and schema checks of every call stay visible
+                        let call = self.to_step(event, expected_statuses)?;
+                        step.attachments.extend(call.attachments);
+                        step.steps.extend(call.steps);
+                        step.stop = step.stop.max(call.stop);
+                        if self.http_parameters {
+                            set_call_count(step, *count);
+                        }
+                        continue;
+                    }
+                }
+                last_call = Some((key.0, key.1, key.2, 1));
+            } else {
+                last_call = None;
+            }
+
+            let mut step = self.to_step(event, expected_statuses)?;
+            step.roll_up_timing();
+            let group = match event {
+                Event::Http(log) => Some(format!(
+                    "{} {}",
+                    log.request.method,
+                    log.request.url.host_str().unwrap_or_default()
+                )),
+                Event::Check(_) => None,
+            };
+            steps.push((group, step));
+        }
+
+        if self.step_grouping {
+            Ok(group_steps(steps))
+        } else {
+            Ok(steps.into_iter().map(|(_, step)| step).collect())
+        }
+    }
+
+    /// Builds the `stdout`/`stderr` attachments from the test's captured output
+    fn output_attachments(
+        &self,
+        project: &str,
+        module: &str,
+        test_name: &str,
+    ) -> eyre::Result<Vec<Attachment>> {
+        let mut attachments = Vec::new();
+        // Taken even when not attached, so that the output of finished tests is not kept
+        let captured = output::take(project, module, test_name);
+        if let Some(captured) = captured.filter(|_| self.attach_output) {
+            for (name, text) in [("stdout", captured.stdout), ("stderr", captured.stderr)] {
+                if !text.is_empty() {
+                    attachments.push(self.write_attachment(name, "text/plain", text.as_bytes())?);
+                }
+            }
+        }
+        Ok(attachments)
+    }
+
+    /// Writes a JSON array with the name and timing of every HTTP call, in call order
+    fn timeline_attachment(&self, events: &[Event]) -> eyre::Result<Option<Attachment>> {
+        let timeline: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Http(log) => {
+                    let (start, stop) = time_span(log.started_at, log.ended_at);
+                    Some(TimelineEntry {
+                        name: format!("{} {}", log.request.method, log.request.url),
+                        start,
+                        stop,
+                    })
+                }
+                Event::Check(_) => None,
+            })
+            .collect();
+        if timeline.is_empty() {
+            return Ok(None);
+        }
+
+        let json = serde_json::to_vec(&timeline)?;
+        Ok(Some(self.write_attachment(
+            "timeline",
+            "application/json",
+            &json,
+        )?))
+    }
+
+    /// Writes all HTTP calls of a test as a HAR 1.2 log, with sensitive headers masked
+    fn har_attachment(&self, events: &[Event]) -> eyre::Result<Option<Attachment>> {
+        let entries: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Http(log) => Some(self.har_entry(log)),
+                Event::Check(_) => None,
+            })
+            .collect();
+        if entries.is_empty() {
+            return Ok(None);
+        }
+
+        let har = serde_json::json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": entries,
+            }
+        });
+        let json = self.to_json(&har)?.into_bytes();
+        Ok(Some(self.write_attachment(
+            "requests.har",
+            "application/json",
+            &json,
+        )?))
+    }
+
+    fn har_entry(&self, log: &http::Log) -> serde_json::Value {
+        let time = log.response.duration_req.as_secs_f64() * 1000.0;
+        let query: Vec<_> = log
+            .request
+            .url
+            .query_pairs()
+            .map(|(name, value)| serde_json::json!({ "name": name, "value": value }))
+            .collect();
+        // Sizes are those of the captured bodies, before redaction and truncation
+        let request_size = log.request.body.as_ref().map_or(0, String::len);
+        let response_size = log.response.body.len();
+        let request_body = log.request.body.as_deref().unwrap_or_default();
+        let request_body = self.redact_body(&log.request.headers, request_body);
+        let response_body = self.redact_body(&log.response.headers, &log.response.body);
+        let response_body = self.truncate_body(&response_body);
+        let mut request = serde_json::json!({
+            "method": log.request.method.as_str(),
+            "url": log.request.url.as_str(),
+            "httpVersion": HAR_UNKNOWN_HTTP_VERSION,
+            "cookies": [],
+            "headers": har_headers(&log.request.headers, &self.header_masking),
+            "queryString": query,
+            "headersSize": -1,
+            "bodySize": request_size,
+        });
+        if log.request.body.is_some() {
+            // Kept whole so that the call can be replayed from the HAR
+            request["postData"] = serde_json::json!({
+                "mimeType": content_type(&log.request.headers).unwrap_or_default(),
+                "text": request_body,
+            });
+        }
+
+        serde_json::json!({
+            "startedDateTime": to_rfc3339(log.started_at),
+            "time": time,
+            "request": request,
+            "response": {
+                "status": log.response.status.as_u16(),
+                "statusText": log.response.status.canonical_reason().unwrap_or_default(),
+                "httpVersion": HAR_UNKNOWN_HTTP_VERSION,
+                "cookies": [],
+                "headers": har_headers(&log.response.headers, &self.header_masking),
+                "content": {
+                    "size": response_size,
+                    "mimeType": content_type(&log.response.headers).unwrap_or_default(),
+                    "text": response_body,
+                },
+                "redirectURL": log
+                    .response
+                    .headers
+                    .get(http::header::LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default(),
+                "headersSize": -1,
+                "bodySize": response_size,
+            },
+            "cache": {},
+            // tanu only measures the whole call. HAR 1.2 allows -1 for the optional phases
+            // only, so the call counts as waiting for the response.
+            "timings": {
+                "blocked": -1,
+                "dns": -1,
+                "connect": -1,
+                "ssl": -1,
+                "send": 0,
+                "wait": time,
+                "receive": 0,
+            },
+        })
+    }
+
+    /// Applies the configured redaction to a JSON body, leaving other bodies and JSON that
+    /// does not parse untouched
+    fn redact_body<'a>(&self, headers: &http::header::HeaderMap, body: &'a str) -> Cow<'a, str> {
+        if self.body_redaction.is_empty() {
+            return Cow::Borrowed(body);
+        }
+        let is_json = content_type(headers).is_some_and(|media_type| {
+            media_type.eq_ignore_ascii_case("application/json")
+                || media_type.to_ascii_lowercase().ends_with("+json")
+        });
+        if !is_json {
+            return Cow::Borrowed(body);
+        }
+        let Ok(mut value) = serde_json::from_str::<serde_json::Value>(body) else {
+            return Cow::Borrowed(body);
+        };
+        if !self.body_redaction.redact(&mut value) {
+            return Cow::Borrowed(body);
+        }
+        Cow::Owned(value.to_string())
+    }
+
+    /// Cuts `body` to at most `max_body_bytes`, backing off to a character boundary
+    fn truncate_body<'a>(&self, body: &'a str) -> &'a str {
+        let Some(limit) = self.max_body_bytes else {
+            return body;
+        };
+        let mut end = usize::try_from(limit).map_or(body.len(), |limit| limit.min(body.len()));
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        &body[..end]
+    }
+
+    fn map_to_allure_fixture_result(
+        &self,
+        project: &str,
+        module: &str,
+        test_name: &str,
+        events: &[Event],
+        test: &Test,
+    ) -> eyre::Result<FixtureResult> {
+        let (start, stop) = time_span(test.started_at, test.ended_at);
+        Ok(FixtureResult {
+            name: display_test_name(project, module, test_name, test, &self.warned_unnamed).0,
+            parameters: Default::default(),
+            attachments: self.output_attachments(project, module, test_name)?,
+            status: self.to_test_status(test),
+            status_details: to_status_details(test),
+            stage: Some(Stage::Finished),
+            start: Some(start),
+            stop: Some(stop),
+            steps: self.to_steps(events, &expected_statuses(&self.tags(test)))?,
+        })
+    }
+
+    fn map_to_allure_test_result(
+        &self,
+        project: &str,
+        module: &str,
+        test_name: &str,
+        buffer: &Buffer,
+        test: &Test,
+    ) -> eyre::Result<TestResult> {
+        let events = &buffer.events;
+        let status = self.to_test_status(test);
+        let mut status_details = to_status_details(test);
+        let empty_details = || StatusDetails {
+            known: None,
+            muted: None,
+            flaky: None,
+            message: None,
+            trace: None,
+        };
+        // Attempts share their history_id, so Allure groups them as retries. Every retried
+        // attempt is flaky, the last one only if its outcome differs from an earlier one:
+        // failing every time is a consistent failure.
+        if buffer.retried
+            || buffer
+                .earlier_statuses
+                .iter()
+                .any(|earlier| *earlier != status)
+        {
+            status_details.get_or_insert_with(empty_details).flaky = Some(true);
+        }
+        let tags = self.tags(test);
+        let known_issues = known_issues(&tags);
+        if tags.iter().any(|tag| tag == "muted") {
+            status_details.get_or_insert_with(empty_details).muted = Some(true);
+        }
+        if !known_issues.is_empty() {
+            status_details.get_or_insert_with(empty_details).known = Some(true);
+        }
+        let steps = self.to_steps(events, &expected_statuses(&tags))?;
+        let (name, identity) =
+            display_test_name(project, module, test_name, test, &self.warned_unnamed);
 
         // Create parameters first so we can use them for history_id generation
-        let parameters = vec![Parameter {
-            name: "Project".to_string(),
-            value: project.to_string(),
-            excluded: Some(true), // Exclude from history_id calculation
-            mode: Default::default(),
-        }];
+        let mut parameters = Vec::new();
+        if self.project_parameter {
+            parameters.push(Parameter {
+                name: "Project".to_string(),
+                value: project.to_string(),
+                excluded: Some(true), // Exclude from history_id calculation
+                mode: Default::default(),
+            });
+        }
+
+        let environment = if self.environment_label {
+            target_environment(project, events)
+        } else {
+            None
+        };
+        if let Some(environment) = &environment {
+            parameters.push(Parameter {
+                name: "Environment".to_string(),
+                value: environment.clone(),
+                excluded: Some(!self.environment_in_history_id),
+                mode: Default::default(),
+            });
+        }
+
+        // Generate deterministic history_id based on test identity
+        let history_id = generate_history_id_with(
+            project,
+            module,
+            &identity,
+            &parameters,
+            &self.history_id_config,
+        );
+        if self.is_flaky_in_history(&history_id) {
+            status_details.get_or_insert_with(empty_details).flaky = Some(true);
+        }
+
+        if self.start_order_parameter {
+            parameters.push(Parameter {
+                name: "start_order".to_string(),
+                value: buffer.start_order.to_string(),
+                excluded: Some(true),
+                mode: Default::default(),
+            });
+        }
+
+        let mut labels = vec![Label::ParentSuite(project.to_string())];
+        if self.bdd_labels {
+            labels.extend(module_labels(module));
+        } else {
+            labels.push(Label::Suite(module.to_string()));
+        }
+        labels.extend(self.global_labels.iter().cloned());
+        if self.host_thread_labels {
+            labels.push(Label::Host(
+                hostname::get()
+                    .ok()
+                    .map(|h| h.to_string_lossy().into_owned())
+                    .filter(|h| !h.is_empty())
+                    .unwrap_or_else(|| "localhost".to_string()),
+            ));
+            labels.push(Label::Thread(test.worker_id.to_string()));
+        }
+        if let Some(environment) = environment {
+            labels.push(Label::custom("environment", environment));
+        }
+        let tag_labels: Vec<_> = tags
+            .iter()
+            .filter(|tag| !is_reporter_tag(tag))
+            .map(|tag| Label::from_tag(tag))
+            .collect();
+        let tag_severity = tag_labels.iter().find_map(|label| match label {
+            Label::Severity(severity) => Some(severity.clone()),
+            _ => None,
+        });
+        labels.extend(tag_labels);
+        // The hook is more specific than a `severity:` tag, which beats the default
+        let severity = self
+            .severity_fn
+            .as_ref()
+            .and_then(|severity_fn| severity_fn(test))
+            .or(tag_severity)
+            .or_else(|| self.default_severity.clone());
+        if let Some(severity) = severity {
+            labels.retain(|label| !matches!(label, Label::Severity(_)));
+            labels.push(Label::Severity(known_severity(&severity, &name)));
+        }
+        if self.service_labels {
+            labels.extend(
+                service_hosts(events)
+                    .into_iter()
+                    .map(|host| Label::custom("service", host)),
+            );
+        }
+        if self.version_label {
+            labels.push(Label::custom(
+                "tanu_allure_version",
+                env!("CARGO_PKG_VERSION"),
+            ));
+        }
+        if buffer.retried || buffer.attempt > 1 {
+            labels.push(Label::custom("retry", "true"));
+            labels.push(Label::custom("attempt", buffer.attempt.to_string()));
+        }
+        // A global label may repeat one derived from the test, e.g. a tag
+        let mut unique = Vec::with_capacity(labels.len());
+        for label in labels {
+            if !unique.contains(&label) {
+                unique.push(label);
+            }
+        }
+        let labels = unique;
+
+        let mut attachments = self.output_attachments(project, module, test_name)?;
+        if self.attach_timeline {
+            attachments.extend(self.timeline_attachment(events)?);
+        }
+        if self.attach_har {
+            attachments.extend(self.har_attachment(events)?);
+        }
+
+        // `class#method`, as Allure's Java integrations write it, with the module path
+        // qualified by the project standing in for the class
+        let full_name = format!("{project}::{module}#{identity}");
+        let description = self
+            .description_fn
+            .as_ref()
+            .and_then(|description_fn| description_fn(test));
+        let test_case_id =
+            generate_test_case_id(project, module, &identity, self.test_case_id_algo);
+        let mut links = self
+            .links_fn
+            .as_ref()
+            .map(|links_fn| links_fn(test))
+            .unwrap_or_default();
+        links.extend(
+            known_issues
+                .into_iter()
+                .filter_map(|issue| self.issue_link(issue)),
+        );
+        let (start, stop) = time_span(test.started_at, test.ended_at);
+
+        Ok(TestResult {
+            uuid: (self.uuid_fn)(),
+            history_id,
+            test_case_id: Some(test_case_id),
+            name,
+            full_name: Some(full_name),
+            description_html: description.as_deref().and_then(markdown_to_html),
+            description,
+            links,
+            labels,
+            parameters,
+            attachments,
+            status,
+            status_details,
+            stage: Some(Stage::Finished),
+            start: Some(start),
+            stop: Some(stop),
+            steps,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Reporter for AllureReporter {
+    async fn on_start(
+        &mut self,
+        project_name: String,
+        module_name: String,
+        test_name: String,
+    ) -> eyre::Result<()> {
+        self.initialize()?;
+        self.buffer_mut(&project_name, &module_name, &test_name);
+        Ok(())
+    }
+
+    async fn on_check(
+        &mut self,
+        project_name: String,
+        module_name: String,
+        test_name: String,
+        check: Box<runner::Check>,
+    ) -> eyre::Result<()> {
+        self.buffer_mut(&project_name, &module_name, &test_name)
+            .events
+            .push(Event::Check(check));
+        Ok(())
+    }
+
+    async fn on_call(
+        &mut self,
+        project_name: String,
+        module_name: String,
+        test_name: String,
+        log: runner::CallLog,
+    ) -> eyre::Result<()> {
+        if let runner::CallLog::Http(http_log) = log {
+            self.buffer_mut(&project_name, &module_name, &test_name)
+                .events
+                .push(Event::Http(http_log));
+        }
+        Ok(())
+    }
+
+    async fn on_retry(
+        &mut self,
+        project: String,
+        module: String,
+        test_name: String,
+        test: Test,
+    ) -> eyre::Result<()> {
+        let status = self.to_test_status(&test);
+        let buffer = self.buffer_mut(&project, &module, &test_name);
+        let finished = Buffer {
+            events: std::mem::take(&mut buffer.events),
+            attempt: buffer.attempt,
+            start_order: buffer.start_order,
+            earlier_statuses: buffer.earlier_statuses.clone(),
+            retried: true,
+        };
+        buffer.attempt += 1;
+        buffer.earlier_statuses.push(status);
+        self.retries += 1;
+
+        if self.fixture_kind(&test).is_some() {
+            // Only the final attempt of a fixture is reported in its container
+            return Ok(());
+        }
+        if self.prepare_error.is_some() {
+            return Ok(());
+        }
+
+        // Each failed attempt is written as its own result; Allure groups them as retries
+        // because they share the same history_id.
+        match self.write_test_result(&project, &module, &test_name, &finished, &test) {
+            Ok(run_result) => self.track_run_result(project, module, run_result)?,
+            Err(e) => self.record_write_failure(&project, &module, &test_name, e),
+        }
+        Ok(())
+    }
+
+    async fn on_end(
+        &mut self,
+        project: String,
+        module: String,
+        test_name: String,
+        test: Test,
+    ) -> eyre::Result<()> {
+        self.initialize()?;
+        // Taking the entry out frees the test's events once its result is written; a test
+        // that was never reported as started still gets a result, without steps
+        let buffer =
+            match self
+                .buffer
+                .swap_remove(&(project.clone(), module.clone(), test_name.clone()))
+            {
+                Some(buffer) => buffer,
+                None => {
+                    self.next_start_order += 1;
+                    Buffer::new(self.next_start_order)
+                }
+            };
+
+        if let Some(kind) = self.fixture_kind(&test) {
+            let fixture = self.map_to_allure_fixture_result(
+                &project,
+                &module,
+                &test_name,
+                &buffer.events,
+                &test,
+            )?;
+            let container = self.container_mut(project, module);
+            extend_span(container, fixture.start, fixture.stop);
+            match kind {
+                FixtureKind::Before => container.befores.push(fixture),
+                FixtureKind::After => container.afters.push(fixture),
+            }
+            return Ok(());
+        }
+        if self.prepare_error.is_some() {
+            return Ok(());
+        }
+
+        match self.write_test_result(&project, &module, &test_name, &buffer, &test) {
+            Ok(run_result) => self.track_run_result(project, module, run_result)?,
+            Err(e) => self.record_write_failure(&project, &module, &test_name, e),
+        }
+        Ok(())
+    }
+
+    async fn on_summary(&mut self, summary: runner::TestSummary) -> eyre::Result<()> {
+        if std::mem::replace(&mut self.finalized, true) {
+            // Appending to the trends twice would record the run twice
+            return Ok(());
+        }
+        // A run without tests never reaches on_start
+        self.initialize()?;
+        if let Some(error) = &self.prepare_error {
+            eyre::bail!("{error}");
+        }
+        self.write_containers()?;
+        if self.shared_results_dir {
+            self.merge_shared_files()?;
+        } else {
+            self.write_history()?;
+            self.write_trends()?;
+            self.write_results_index()?;
+        }
+
+        let written = self.run_summary();
+        if let Some(finish_fn) = &self.finish_fn {
+            finish_fn(&summary, &written);
+        }
+        if self.print_summary {
+            eprintln!("tanu-allure: {}", written.to_line());
+        }
+        if !self.write_failures.is_empty() {
+            eyre::bail!(
+                "failed to write the results of {} test(s): {}",
+                self.write_failures.len(),
+                self.write_failures.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+impl AllureReporter {
+    /// Converts the buffered events of one test attempt into a result file and returns
+    /// the entry to track for the history update
+    fn write_test_result(
+        &self,
+        project: &str,
+        module: &str,
+        test_name: &str,
+        buffer: &Buffer,
+        test: &Test,
+    ) -> eyre::Result<RunResult> {
+        let test_result =
+            self.map_to_allure_test_result(project, module, test_name, buffer, test)?;
+        self.write_result(&test_result)
+    }
+
+    /// Validates and writes a result, returning the entry to track for the history update
+    fn write_result(&self, test_result: &TestResult) -> eyre::Result<RunResult> {
+        let name = test_result
+            .full_name
+            .as_deref()
+            .unwrap_or(&test_result.name);
+        // Allure silently drops a result without history_id from trends and retries
+        eyre::ensure!(
+            !test_result.history_id.is_empty(),
+            "result of \"{name}\" ({}) has an empty history_id",
+            test_result.uuid
+        );
+
+        let json = self.to_json(test_result).wrap_err_with(|| {
+            format!(
+                "failed to serialize result of \"{name}\" ({})",
+                test_result.uuid
+            )
+        })?;
+
+        self.write_result_file(&test_result.uuid, json.as_bytes())
+            .wrap_err_with(|| {
+                format!(
+                    "failed to write result of \"{name}\" ({})",
+                    test_result.uuid
+                )
+            })?;
+        // The result file is written, so a failing copy on stdout must not fail the result
+        if self.stdout_json {
+            if let Err(e) = write_json_line(test_result) {
+                eprintln!(
+                    "tanu-allure: failed to print result of \"{name}\" ({}) to stdout: {e}",
+                    test_result.uuid
+                );
+            }
+        }
+
+        Ok(RunResult {
+            history_id: test_result.history_id.clone(),
+            status: test_result.status.clone(),
+            status_details: test_result
+                .status_details
+                .as_ref()
+                .and_then(|d| d.message.clone()),
+            muted: test_result
+                .status_details
+                .as_ref()
+                .is_some_and(|d| d.muted == Some(true)),
+            start: test_result.start.unwrap_or(0),
+            stop: test_result.stop.unwrap_or(0),
+            uuid: test_result.uuid,
+            full_name: name.to_string(),
+        })
+    }
+
+    fn fixture_kind(&self, test: &Test) -> Option<FixtureKind> {
+        self.fixture_fn
+            .as_ref()
+            .and_then(|fixture_fn| fixture_fn(test))
+    }
+
+    fn container_mut(&mut self, project: String, module: String) -> &mut TestResultContainer {
+        let uuid_fn = &self.uuid_fn;
+        self.containers
+            .entry((project, module))
+            .or_insert_with_key(|(project, module)| TestResultContainer {
+                uuid: uuid_fn(),
+                name: Some(format!("{project}::{module}")),
+                ..Default::default()
+            })
+    }
+
+    /// Reports a result that could not be written; the run fails in `on_summary`
+    fn record_write_failure(
+        &mut self,
+        project: &str,
+        module: &str,
+        test_name: &str,
+        error: eyre::Report,
+    ) {
+        let test = format!("{project}::{module}::{test_name}");
+        eprintln!(
+            "tanu-allure: failed to write the result of {test} to {}: {error:#}",
+            self.results_dir
+        );
+        self.write_failures.push(test);
+    }
+
+    /// Tracks a written result for the history update and, when fixtures are routed,
+    /// as a child of its module's container
+    fn track_run_result(
+        &mut self,
+        project: String,
+        module: String,
+        run_result: RunResult,
+    ) -> eyre::Result<()> {
+        if self.incremental_history {
+            self.append_history(std::slice::from_ref(&run_result))?;
+        }
+        if self.fixture_fn.is_some() {
+            let container = self.container_mut(project, module);
+            container.children.push(run_result.uuid);
+            extend_span(container, Some(run_result.start), Some(run_result.stop));
+        }
+        self.current_run_results.push(run_result);
+        Ok(())
+    }
+
+    /// Prepares the results directory before the first test: creates it, removes stale
+    /// files if cleaning is enabled and writes the files that do not depend on results
+    ///
+    /// An invalid configuration or a destination that cannot be prepared is reported once,
+    /// here, and again as the error of `on_summary`; the tests keep running but none of
+    /// their results are written.
+    fn initialize(&mut self) -> eyre::Result<()> {
+        if self.initialized || self.prepare_error.is_some() {
+            return Ok(());
+        }
+        if let Err(e) = self.validate() {
+            eprintln!("tanu-allure: {e}; no results will be written");
+            self.prepare_error = Some(e.to_string());
+            return Ok(());
+        }
+        if let Err(e) = self.sink.prepare() {
+            let error = format!("failed to prepare the results destination: {e}");
+            eprintln!("tanu-allure: {error}; no results will be written");
+            self.prepare_error = Some(error);
+            return Ok(());
+        }
+        self.initialized = true;
+        if self.clean {
+            self.clean_results_dir()?;
+        }
+        #[cfg(feature = "zstd")]
+        if matches!(self.compression, Compression::Zstd) {
+            self.sink
+                .write_file("COMPRESSED.txt", COMPRESSED_NOTE.as_bytes())?;
+        }
+        self.write_environment()?;
+        self.write_categories()?;
+        self.write_executor()?;
+        Ok(())
+    }
+
+    /// Rejects options that cannot be combined
+    pub(crate) fn validate(&self) -> eyre::Result<()> {
+        if self.clean && self.shared_results_dir {
+            eyre::bail!(
+                "with_clean cannot be combined with shared_results_dir, as it would delete the \
+                 results of the other writers"
+            );
+        }
+        if self.custom_sink && (self.incremental_history || self.shared_results_dir) {
+            eyre::bail!(
+                "incremental_history and shared_results_dir merge the history files in \
+                 results_dir and cannot be combined with with_sink"
+            );
+        }
+        Ok(())
+    }
+
+    /// Deletes the per-test files of previous runs, see [`with_clean`](Self::with_clean)
+    fn clean_results_dir(&self) -> eyre::Result<()> {
+        let entries = match fs::read_dir(&self.results_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let is_stale = name.to_str().is_some_and(|name| {
+                STALE_FILE_SUFFIXES
+                    .iter()
+                    .any(|suffix| name.ends_with(suffix))
+            });
+            if is_stale && entry.file_type()?.is_file() {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Serializes `value` for a file in `results_dir`, indented unless configured otherwise
+    fn to_json<T: Serialize + ?Sized>(&self, value: &T) -> serde_json::Result<String> {
+        if self.pretty {
+            serde_json::to_string_pretty(value)
+        } else {
+            serde_json::to_string(value)
+        }
+    }
+
+    /// Writes the serialized result of `uuid`, compressing it if configured
+    fn write_result_file(&self, uuid: &uuid::Uuid, json: &[u8]) -> eyre::Result<()> {
+        match self.compression {
+            Compression::None => self
+                .sink
+                .write_result(&format!("{uuid}-result.json"), json)?,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let compressed = zstd::encode_all(json, 0)
+                    .wrap_err_with(|| format!("failed to compress result {uuid}"))?;
+                self.sink
+                    .write_result(&format!("{uuid}-result.json.zst"), &compressed)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a `{uuid}-container.json` for every module that reported fixtures
+    fn write_containers(&self) -> eyre::Result<()> {
+        for container in self.containers.values() {
+            if container.befores.is_empty() && container.afters.is_empty() {
+                continue;
+            }
+            let file_name = format!("{}-container.json", container.uuid);
+            let json = self.to_json(container).wrap_err_with(|| {
+                format!(
+                    "failed to serialize container of \"{}\" ({})",
+                    container.name.as_deref().unwrap_or_default(),
+                    container.uuid
+                )
+            })?;
+            self.sink.write_file(&file_name, json.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Writes updated history.json after all tests complete
+    fn write_history(&mut self) -> eyre::Result<()> {
+        if self.incremental_history {
+            // Every result has already been merged into history.json as it finished
+            return Ok(());
+        }
+
+        for result in &self.current_run_results {
+            record_history(&mut self.history, result);
+        }
+
+        let json = self.to_json(&self.history)?;
+        self.sink
+            .write_file("history/history.json", json.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Prepends this run to `history/duration-trend.json` and `history/retry-trend.json`
+    fn write_trends(&mut self) -> eyre::Result<()> {
+        let start = self.current_run_results.iter().map(|r| r.start).min();
+        let stop = self.current_run_results.iter().map(|r| r.stop).max();
+        let (Some(start), Some(stop)) = (start, stop) else {
+            // Nothing ran, so there is no data point to record
+            return Ok(());
+        };
+        if self.shared_results_dir {
+            // Another writer may have added its run since this one started
+            let history_dir = Path::new(&self.results_dir).join("history");
+            if history_dir.join("duration-trend.json").exists() {
+                self.duration_trend = Self::load_trend(&history_dir, "duration-trend.json");
+            }
+            if history_dir.join("retry-trend.json").exists() {
+                self.retry_trend = Self::load_trend(&history_dir, "retry-trend.json");
+            }
+        }
+
+        let duration = self.trend_item(DurationTrendData {
+            duration: stop - start,
+        });
+        push_trend(&mut self.duration_trend, duration);
+        let retry = self.trend_item(RetryTrendData {
+            run: self.current_run_results.len() as u32,
+            retry: self.retries,
+        });
+        push_trend(&mut self.retry_trend, retry);
+
+        let json = self.to_json(&self.duration_trend)?;
+        self.sink
+            .write_file("history/duration-trend.json", json.as_bytes())?;
+        let json = self.to_json(&self.retry_trend)?;
+        self.sink
+            .write_file("history/retry-trend.json", json.as_bytes())?;
+        Ok(())
+    }
+
+    /// Wraps the data point of this run, linking it to the executor's build if configured
+    fn trend_item<T>(&self, data: T) -> TrendItem<T> {
+        TrendItem {
+            data,
+            build_order: self.executor.as_ref().and_then(|e| e.build_order),
+            report_url: self.executor.as_ref().and_then(|e| e.report_url.clone()),
+            report_name: self.executor.as_ref().and_then(|e| e.report_name.clone()),
+        }
+    }
+
+    /// Merges results into history.json on disk, see [`lock_history`](Self::lock_history)
+    fn append_history(&self, results: &[RunResult]) -> eyre::Result<()> {
+        let _lock = self.lock_history()?;
+        self.merge_history(results)
+    }
+
+    /// Merges this run's history, trends and results index into the files of a
+    /// [shared](Self::shared_results_dir) `results_dir`, all under one lock
+    fn merge_shared_files(&mut self) -> eyre::Result<()> {
+        let _lock = self.lock_history()?;
+        if !self.incremental_history {
+            self.merge_history(&self.current_run_results)?;
+        }
+        self.write_trends()?;
+        self.write_results_index()?;
+        Ok(())
+    }
+
+    /// Takes the exclusive lock that serializes updates of the history files, released when
+    /// the returned file is dropped.
+    ///
+    /// The files are re-read while it is held so that concurrent writers sharing the
+    /// results directory serialize their updates instead of overwriting each other.
+    fn lock_history(&self) -> eyre::Result<fs::File> {
+        let history_dir = Path::new(&self.results_dir).join("history");
+        fs::create_dir_all(&history_dir)?;
+
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(history_dir.join(".history.json.lock"))?;
+        lock.lock()?;
+        Ok(lock)
+    }
+
+    /// Re-reads history.json and writes it back with `results` added; the caller holds the
+    /// lock
+    fn merge_history(&self, results: &[RunResult]) -> eyre::Result<()> {
+        let history_dir = Path::new(&self.results_dir).join("history");
+        let mut history = if history_dir.join("history.json").exists() {
+            Self::load_history(&history_dir)
+        } else {
+            self.history.clone()
+        };
+        for result in results {
+            record_history(&mut history, result);
+        }
+
+        let json = self.to_json(&history)?;
+        sink::write_atomically(&history_dir.join("history.json"), json.as_bytes())?;
+        Ok(())
+    }
+
+    /// Writes results-index.json if enabled, keeping the entries of other writers in a
+    /// shared `results_dir`
+    fn write_results_index(&self) -> eyre::Result<()> {
+        if !self.results_index {
+            return Ok(());
+        }
+        let path = Path::new(&self.results_dir).join("results-index.json");
+        let mut index = serde_json::Map::new();
+        if self.shared_results_dir && path.exists() {
+            let parsed = fs::read_to_string(&path)
+                .map_err(eyre::Report::from)
+                .and_then(|s| serde_json::from_str(&s).map_err(eyre::Report::from));
+            match parsed {
+                Ok(existing) => index = existing,
+                Err(e) => eprintln!(
+                    "tanu-allure: ignoring unreadable {}, starting a new index: {e}",
+                    path.display()
+                ),
+            }
+        }
+        index.extend(
+            self.current_run_results
+                .iter()
+                .map(|result| (result.uuid.to_string(), result.full_name.clone().into())),
+        );
+        let json = self.to_json(&index)?;
+        self.sink
+            .write_file("results-index.json", json.as_bytes())?;
+        Ok(())
+    }
+
+    /// Writes categories.json if categories are configured
+    fn write_categories(&self) -> eyre::Result<()> {
+        if self.categories.is_empty() {
+            return Ok(());
+        }
+        let json = self.to_json(&self.categories)?;
+        self.sink.write_file("categories.json", json.as_bytes())?;
+        Ok(())
+    }
+
+    /// Writes executor.json if an executor is configured
+    fn write_executor(&self) -> eyre::Result<()> {
+        let Some(executor) = &self.executor else {
+            return Ok(());
+        };
+        let json = self.to_json(executor)?;
+        self.sink.write_file("executor.json", json.as_bytes())?;
+        Ok(())
+    }
+
+    /// Writes environment.properties file with environment variables
+    fn write_environment(&self) -> eyre::Result<()> {
+        if self.environment.is_empty() {
+            return Ok(());
+        }
+
+        // Build properties file content
+        let mut lines: Vec<String> = self
+            .environment
+            .iter()
+            .map(|(key, value)| {
+                // Escape special characters for Java properties format
+                let escaped_key = key
+                    .replace('\\', "\\\\")
+                    .replace('=', "\\=")
+                    .replace(':', "\\:");
+                let escaped_value = value
+                    .replace('\\', "\\\\")
+                    .replace('\n', "\\n")
+                    .replace('\r', "\\r");
+                format!("{} = {}", escaped_key, escaped_value)
+            })
+            .collect();
+
+        // Sort for deterministic output
+        lines.sort();
+
+        // Write environment.properties file
+        self.sink
+            .write_file("environment.properties", lines.join("\n").as_bytes())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::BTreeMap,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::{Duration, SystemTime},
+    };
+
+    /// Keeps every written file in memory, keyed by its name
+    #[derive(Clone, Default)]
+    struct MemorySink(Arc<Mutex<BTreeMap<String, Vec<u8>>>>);
+
+    impl MemorySink {
+        fn results(&self) -> Vec<TestResult> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| name.ends_with("-result.json"))
+                .map(|(_, contents)| serde_json::from_slice(contents).unwrap())
+                .collect()
+        }
+
+        fn file(&self, name: &str) -> Vec<u8> {
+            self.0.lock().unwrap()[name].clone()
+        }
+
+        /// Names of the attachment files written
+        fn attachment_files(&self) -> Vec<String> {
+            let files = self.0.lock().unwrap();
+            files
+                .keys()
+                .filter(|name| name.contains("-attachment"))
+                .cloned()
+                .collect()
+        }
+
+        fn containers(&self) -> Vec<TestResultContainer> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| name.ends_with("-container.json"))
+                .map(|(_, contents)| serde_json::from_slice(contents).unwrap())
+                .collect()
+        }
+    }
+
+    impl ResultSink for MemorySink {
+        fn write_file(&self, file_name: &str, contents: &[u8]) -> io::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(file_name.to_string(), contents.to_vec());
+            Ok(())
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("tanu-allure-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    /// Uuids counting up from `…0001`, so that file names are the same in every run
+    fn sequential_uuids() -> impl Fn() -> uuid::Uuid + Send + 'static {
+        let next = AtomicU64::new(1);
+        move || uuid::Uuid::from_u64_pair(0, next.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn reporter() -> (AllureReporter, MemorySink) {
+        let sink = MemorySink::default();
+        let reporter = AllureReporter::with_results_dir(temp_dir().to_string_lossy())
+            .with_sink(sink.clone())
+            .with_uuid_fn(sequential_uuids());
+        (reporter, sink)
+    }
+
+    fn http_log(url: &str, request_body: Option<&str>, response_body: &str) -> http::Log {
+        let now = SystemTime::now();
+        http::Log {
+            request: http::LogRequest {
+                url: url.parse().unwrap(),
+                method: http::Method::POST,
+                headers: http::header::HeaderMap::new(),
+                body: request_body.map(str::to_string),
+            },
+            response: http::LogResponse {
+                headers: http::header::HeaderMap::new(),
+                body: response_body.to_string(),
+                status: http::StatusCode::OK,
+                duration_req: Duration::from_millis(5),
+            },
+            started_at: now,
+            ended_at: now,
+        }
+    }
+
+    fn test_case(name: &str, result: Result<(), runner::Error>) -> Test {
+        let now = SystemTime::now();
+        Test {
+            info: Arc::new(runner::TestInfo {
+                module: "module".to_string(),
+                name: name.to_string(),
+                ..Default::default()
+            }),
+            worker_id: 0,
+            started_at: now,
+            ended_at: now,
+            request_time: Duration::ZERO,
+            result,
+        }
+    }
+
+    fn summary(total_tests: usize) -> runner::TestSummary {
+        runner::TestSummary {
+            total_tests,
+            passed_tests: total_tests,
+            failed_tests: 0,
+            skipped_tests: 0,
+            total_time: Duration::ZERO,
+            test_prep_time: Duration::ZERO,
+        }
+    }
+
+    /// Maps a finished test of `project::module` into its result
+    fn map_test(reporter: &AllureReporter, events: Vec<Event>, test: &Test) -> TestResult {
+        let buffer = Buffer {
+            events,
+            ..Buffer::new(1)
+        };
+        reporter
+            .map_to_allure_test_result("project", "module", &test.info.name, &buffer, test)
+            .unwrap()
+    }
+
+    fn label<'a>(result: &'a TestResult, name: &str) -> Option<&'a str> {
+        result.labels.iter().find_map(|label| match label {
+            Label::Custom { name: n, value } if n == name => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    fn failed() -> Result<(), runner::Error> {
+        Err(runner::Error::ErrorReturned("boom".to_string()))
+    }
+
+    async fn start(reporter: &mut AllureReporter, name: &str) {
+        reporter
+            .on_start("project".into(), "module".into(), name.into())
+            .await
+            .unwrap();
+    }
+
+    /// Hands `event` of the test `name` to `reporter` as tanu would
+    async fn deliver(reporter: &mut AllureReporter, name: &str, event: Event) {
+        let (project, module, name) = ("project".into(), "module".into(), name.into());
+        match event {
+            Event::Check(check) => reporter.on_check(project, module, name, check).await,
+            Event::Http(log) => {
+                let log = runner::CallLog::Http(log);
+                reporter.on_call(project, module, name, log).await
+            }
+        }
+        .unwrap();
+    }
+
+    async fn end(reporter: &mut AllureReporter, name: &str, result: Result<(), runner::Error>) {
+        let test = test_case(name, result);
+        reporter
+            .on_end("project".into(), "module".into(), name.into(), test)
+            .await
+            .unwrap();
+    }
+
+    /// Runs the test `name` of `project::module` through `reporter`: its start, `events`
+    /// in order and its end with `result`
+    async fn run_test(
+        reporter: &mut AllureReporter,
+        name: &str,
+        events: Vec<Event>,
+        result: Result<(), runner::Error>,
+    ) {
+        start(reporter, name).await;
+        for event in events {
+            deliver(reporter, name, event).await;
+        }
+        end(reporter, name, result).await;
+    }
+
+    /// Runs `name` as the only, passing test of `reporter` and finishes the run
+    async fn run_one(reporter: &mut AllureReporter, name: &str) {
+        run_test(reporter, name, Vec::new(), Ok(())).await;
+        reporter.on_summary(summary(1)).await.unwrap();
+    }
+
+    /// Runs `outcomes` as the attempts of one test and returns the written results in order
+    async fn run_attempts(outcomes: Vec<Result<(), runner::Error>>) -> Vec<TestResult> {
+        let (mut reporter, sink) = reporter();
+        let name = "retried";
+        start(&mut reporter, name).await;
+        let mut outcomes = outcomes;
+        let last = outcomes.pop().unwrap();
+        for outcome in outcomes {
+            let test = test_case(name, outcome);
+            reporter
+                .on_retry("project".into(), "module".into(), name.into(), test)
+                .await
+                .unwrap();
+        }
+        end(&mut reporter, name, last).await;
+        let mut results = sink.results();
+        results.sort_by_key(|result| label(result, "attempt").map(str::to_string));
+        results
+    }
+
+    fn parameter<'a>(step: &'a Step, name: &str) -> Option<&'a str> {
+        step.parameters
+            .iter()
+            .find(|parameter| parameter.name == name)
+            .map(|parameter| parameter.value.as_str())
+    }
+
+    #[test]
+    fn http_statuses_map_to_step_statuses() {
+        for (code, status) in [
+            (100, Status::Passed),
+            (101, Status::Passed),
+            (200, Status::Passed),
+            (204, Status::Passed),
+            (301, Status::Passed),
+            (302, Status::Passed),
+            (304, Status::Passed),
+            (307, Status::Passed),
+            (308, Status::Passed),
+            (404, Status::Failed),
+            (422, Status::Failed),
+            (500, Status::Failed),
+            (503, Status::Failed),
+        ] {
+            let code = http::StatusCode::from_u16(code).unwrap();
+            assert_eq!(Status::from(code), status, "{code}");
+        }
+        assert_eq!(
+            http_status(http::StatusCode::CONTINUE, &Status::Skipped),
+            Status::Skipped
+        );
+        assert_eq!(
+            http_status(http::StatusCode::from_u16(999).unwrap(), &Status::Passed),
+            Status::Broken
+        );
+    }
+
+    #[test]
+    fn test_outcomes_map_to_result_statuses() {
+        let panicked = Err(runner::Error::Panicked("boom".to_string()));
+        for (result, status) in [
+            (Ok(()), Status::Passed),
+            (failed(), Status::Failed),
+            (panicked, Status::Broken),
+        ] {
+            assert_eq!(default_test_status(&test_case("mapped", result)), status);
+        }
+    }
+
+    #[test]
+    fn byte_parameters_report_captured_body_sizes() {
+        let (reporter, _) = reporter();
+        let step = reporter
+            .http_step(
+                &http_log("https://example.com/", Some("hello"), "{\"a\":1}"),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(parameter(&step, "request.bytes"), Some("5"));
+        assert_eq!(parameter(&step, "response.bytes"), Some("7"));
+    }
+
+    #[test]
+    fn byte_parameters_are_omitted_for_bodies_not_captured() {
+        let (reporter, _) = reporter();
+        let step = reporter
+            .http_step(&http_log("https://example.com/", None, ""), &[])
+            .unwrap();
+        assert_eq!(parameter(&step, "request.bytes"), None);
+        assert_eq!(parameter(&step, "response.bytes"), None);
+
+        let mut log = http_log("https://example.com/", None, "");
+        log.response
+            .headers
+            .insert(http::header::CONTENT_LENGTH, "0".parse().unwrap());
+        let step = reporter.http_step(&log, &[]).unwrap();
+        assert_eq!(parameter(&step, "response.bytes"), Some("0"));
+    }
+
+    #[test]
+    fn a_header_pattern_masks_every_matching_header() {
+        let (mut reporter, _) = reporter();
+        reporter.add_masked_header_pattern(".*-token").unwrap();
+        let mut log = http_log("https://example.com/", None, "");
+        for (name, value) in [
+            ("x-api-token", "api"),
+            ("x-auth-token", "auth"),
+            ("x-token-id", "id"),
+        ] {
+            log.request.headers.insert(name, value.parse().unwrap());
+        }
+
+        let step = reporter.http_step(&log, &[]).unwrap();
+        assert_eq!(
+            parameter(&step, "request.header.x-api-token"),
+            Some("<masked>")
+        );
+        assert_eq!(
+            parameter(&step, "request.header.x-auth-token"),
+            Some("<masked>")
+        );
+        assert_eq!(parameter(&step, "request.header.x-token-id"), Some("id"));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn a_compressed_result_decompresses_to_the_original_json() {
+        let (reporter, sink) = reporter();
+        let reporter = reporter.compress(Compression::Zstd);
+        let test = test_case("compressed", Ok(()));
+        let json = reporter
+            .to_json(&map_test(&reporter, Vec::new(), &test))
+            .unwrap();
+        let uuid = uuid::Uuid::new_v4();
+        reporter.write_result_file(&uuid, json.as_bytes()).unwrap();
+
+        let files = sink.0.lock().unwrap();
+        assert!(!files.contains_key(&format!("{uuid}-result.json")));
+        let compressed = &files[&format!("{uuid}-result.json.zst")];
+        assert!(compressed.len() < json.len());
+        let decompressed = zstd::decode_all(compressed.as_slice()).unwrap();
+        assert_eq!(String::from_utf8(decompressed).unwrap(), json);
+    }
+
+    #[test]
+    fn minimal_keeps_status_timing_suites_and_steps_only() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.minimal();
+        let events = vec![Event::Http(Box::new(http_log(
+            "https://example.com/items?page=2",
+            Some("hello"),
+            "world",
+        )))];
+        let result = map_test(&reporter, events, &test_case("lean", Ok(())));
+
+        assert_eq!(result.status, Status::Passed);
+        assert!(result.start.is_some() && result.stop.is_some());
+        assert!(result.parameters.is_empty());
+        assert!(result
+            .labels
+            .contains(&Label::ParentSuite("project".to_string())));
+        assert!(result.labels.contains(&Label::Suite("module".to_string())));
+        assert!(!result
+            .labels
+            .iter()
+            .any(|label| matches!(label, Label::Host(_) | Label::Thread(_))));
+
+        let [step] = result.steps.as_slice() else {
+            panic!("expected one step, got {:?}", result.steps);
+        };
+        assert_eq!(step.status, Status::Passed);
+        assert!(step.parameters.is_empty(), "{:?}", step.parameters);
+        assert_eq!(step.attachments.len(), 2);
+    }
+
+    fn is_flaky(result: &TestResult) -> bool {
+        result
+            .status_details
+            .as_ref()
+            .and_then(|details| details.flaky)
+            .unwrap_or(false)
+    }
+
+    #[tokio::test]
+    async fn every_retried_attempt_is_flaky_and_labelled() {
+        let results = run_attempts(vec![failed(), Ok(())]).await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(label(&results[0], "attempt"), Some("1"));
+        assert_eq!(label(&results[0], "retry"), Some("true"));
+        assert!(is_flaky(&results[0]));
+        assert_eq!(label(&results[1], "attempt"), Some("2"));
+        assert!(
+            is_flaky(&results[1]),
+            "the outcome changed on the last attempt"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_second_attempt_is_labelled_as_a_retry() {
+        let results = run_attempts(vec![failed(), failed(), Ok(())]).await;
+        let attempts: Vec<_> = results
+            .iter()
+            .map(|result| (label(result, "retry"), label(result, "attempt")))
+            .collect();
+        assert_eq!(
+            attempts,
+            [
+                (Some("true"), Some("1")),
+                (Some("true"), Some("2")),
+                (Some("true"), Some("3")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_last_attempt_failing_like_the_earlier_ones_is_not_flaky() {
+        let results = run_attempts(vec![failed(), failed()]).await;
+        assert!(is_flaky(&results[0]));
+        assert!(!is_flaky(&results[1]));
+    }
+
+    #[tokio::test]
+    async fn a_test_without_retries_has_no_retry_labels() {
+        let results = run_attempts(vec![Ok(())]).await;
+        assert_eq!(label(&results[0], "retry"), None);
+        assert!(!is_flaky(&results[0]));
+    }
+
+    #[tokio::test]
+    async fn an_unwritable_results_dir_is_reported_once_from_on_summary() {
+        // A path below a regular file cannot be created, even by root
+        let file = temp_dir();
+        fs::write(&file, b"").unwrap();
+        let results_dir = file.join("allure-results");
+        let mut reporter = AllureReporter::with_results_dir(results_dir.to_string_lossy());
+
+        for name in ["first", "second"] {
+            run_test(&mut reporter, name, Vec::new(), Ok(())).await;
+        }
+        assert!(reporter.write_failures.is_empty());
+
+        let error = reporter
+            .on_summary(summary(2))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains(&results_dir.display().to_string()) && error.contains("not writable"),
+            "{error}"
+        );
+        fs::remove_file(file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn on_finish_runs_once_with_what_was_written() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        let (reporter, _) = reporter();
+        let results_dir = reporter.results_dir.clone();
+        let mut reporter = reporter.on_finish(move |summary, written| {
+            recorded
+                .lock()
+                .unwrap()
+                .push((summary.total_tests, written.clone()));
+        });
+
+        run_one(&mut reporter, "finished").await;
+        reporter.on_summary(summary(1)).await.unwrap();
+
+        let calls = calls.lock().unwrap();
+        let [(total_tests, written)] = calls.as_slice() else {
+            panic!("expected one call, got {}", calls.len());
+        };
+        assert_eq!(*total_tests, 1);
+        assert_eq!(written.results, 1);
+        assert_eq!(written.retries, 0);
+        assert!(written.results_dir.is_absolute());
+        assert!(written.results_dir.ends_with(results_dir));
+    }
+
+    /// Runs `name` as the only test of a reporter writing into the shared `results_dir`
+    async fn run_shared(results_dir: &Path, name: &str) {
+        let mut reporter = AllureReporter::with_results_dir(results_dir.to_string_lossy())
+            .shared_results_dir(true)
+            .results_index(true);
+        run_one(&mut reporter, name).await;
+    }
+
+    fn read_json(path: &Path) -> serde_json::Value {
+        serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn two_writers_sharing_a_results_dir_keep_each_others_runs() {
+        let results_dir = Arc::new(temp_dir());
+        let writers: Vec<_> = (0..2)
+            .map(|writer| {
+                let results_dir = results_dir.clone();
+                tokio::spawn(async move {
+                    for run in 0..5 {
+                        run_shared(&results_dir, &format!("writer_{writer}_{run}")).await;
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        let history = read_json(&results_dir.join("history").join("history.json"));
+        assert_eq!(history.as_object().unwrap().len(), 10);
+        let index = read_json(&results_dir.join("results-index.json"));
+        assert_eq!(index.as_object().unwrap().len(), 10);
+        for trend in ["duration-trend.json", "retry-trend.json"] {
+            let trend = read_json(&results_dir.join("history").join(trend));
+            assert_eq!(trend.as_array().unwrap().len(), 10);
+        }
+        fs::remove_dir_all(&*results_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cleaning_a_shared_results_dir_is_rejected() {
+        let results_dir = temp_dir();
+        let mut reporter = AllureReporter::with_results_dir(results_dir.to_string_lossy())
+            .shared_results_dir(true)
+            .with_clean(true);
+        run_test(&mut reporter, "cleaned", Vec::new(), Ok(())).await;
+
+        let error = reporter.on_summary(summary(1)).await.unwrap_err();
+        assert!(error.to_string().contains("shared_results_dir"), "{error}");
+        assert!(!results_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn incremental_history_keeps_tests_of_a_run_that_never_finishes() {
+        let results_dir = temp_dir();
+        let mut reporter = AllureReporter::with_results_dir(results_dir.to_string_lossy())
+            .incremental_history(true);
+        start(&mut reporter, "crashed").await;
+        run_test(&mut reporter, "finished", Vec::new(), Ok(())).await;
+
+        // Neither the last test's on_end nor on_summary ever runs
+        let history = read_json(&results_dir.join("history").join("history.json"));
+        let history = history.as_object().unwrap();
+        assert_eq!(history.len(), 1);
+        let (_, entry) = history.iter().next().unwrap();
+        assert_eq!(entry["statistic"]["passed"], 1);
+        assert_eq!(entry["items"].as_array().unwrap().len(), 1);
+        fs::remove_dir_all(&results_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn incremental_history_is_rejected_with_a_custom_sink() {
+        let (reporter, sink) = reporter();
+        let mut reporter = reporter.incremental_history(true);
+        run_test(&mut reporter, "sunk", Vec::new(), Ok(())).await;
+
+        let error = reporter.on_summary(summary(1)).await.unwrap_err();
+        assert!(error.to_string().contains("with_sink"), "{error}");
+        assert!(sink.0.lock().unwrap().is_empty());
+        assert!(!Path::new(&reporter.results_dir).exists());
+    }
+
+    #[tokio::test]
+    async fn each_run_adds_an_item_to_the_history() {
+        let results_dir = temp_dir();
+        let history_path = results_dir.join("history").join("history.json");
+        for run in 1..=2 {
+            let mut reporter = AllureReporter::with_results_dir(results_dir.to_string_lossy());
+            run_one(&mut reporter, "repeated").await;
+
+            let history = read_json(&history_path);
+            let history = history.as_object().unwrap();
+            assert_eq!(history.len(), 1);
+            let (_, entry) = history.iter().next().unwrap();
+            assert_eq!(entry["items"].as_array().unwrap().len(), run);
+            assert_eq!(entry["statistic"]["passed"], run);
+            assert_eq!(entry["statistic"]["total"], run);
+        }
+        fs::remove_dir_all(&results_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_malformed_history_is_replaced_by_a_new_one() {
+        let results_dir = temp_dir();
+        let history_path = results_dir.join("history").join("history.json");
+        fs::create_dir_all(history_path.parent().unwrap()).unwrap();
+        fs::write(&history_path, "{ not json").unwrap();
+
+        let mut reporter = AllureReporter::with_results_dir(results_dir.to_string_lossy());
+        run_one(&mut reporter, "fresh").await;
+
+        let history = read_json(&history_path);
+        let (_, entry) = history.as_object().unwrap().iter().next().unwrap();
+        assert_eq!(entry["items"].as_array().unwrap().len(), 1);
+        fs::remove_dir_all(&results_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_fully_configured_builder_chain_builds_a_working_reporter() {
+        let sink = MemorySink::default();
+        let mut reporter = crate::AllureReporterBuilder::new(temp_dir().to_string_lossy())
+            .with_sink(sink.clone())
+            .with_environment_value("stage", "ci")
+            .with_environment_prefix("TANU_ALLURE_BUILDER_TEST_")
+            .with_masked_header("x-session-id")
+            .with_masked_headers(["authorization"])
+            .with_masked_header_pattern(".*-token")
+            .with_masked_query_param("sig")
+            .with_redacted_body_key("password")
+            .with_redacted_body_key_pattern("secret_.*")
+            .with_max_body_bytes(1024)
+            .with_attachment_compression(4096)
+            .with_inline_attachments(64)
+            .with_max_header_bytes(256)
+            .with_test_case_id_algo(HashAlgo::Sha256)
+            .with_fixture_fn(|_| None)
+            .with_finish_fn(|_, _| {})
+            .with_informational_status(Status::Skipped)
+            .with_error_mapper(default_error_status)
+            .with_environment_label(true)
+            .with_environment_in_history_id(false)
+            .with_timeline_attachment(true)
+            .with_skipped_body_types(vec!["image/png".to_string()])
+            .with_media_type_overrides(HashMap::new())
+            .with_compression(Compression::None)
+            .with_coalesced_http_calls(true)
+            .with_tags_fn(|_| vec!["smoke".to_string()])
+            .with_start_order_parameter(true)
+            .with_version_label(true)
+            .with_history_id_components(HistoryIdConfig::default())
+            .with_har_attachment(true)
+            .with_project_parameter(true)
+            .with_host_thread_labels(true)
+            .with_header_parameters(true)
+            .with_http_parameters(true)
+            .with_service_labels(true)
+            .with_default_categories()
+            .with_history_dir(temp_dir())
+            .with_severity_fn(|_| None)
+            .with_default_severity("minor")
+            .with_clean(true)
+            .with_executor(Executor::default())
+            .with_step_grouping(true)
+            .with_description_fn(|_| Some("configured".to_string()))
+            .with_links_fn(|_| Vec::new())
+            .with_bdd_labels(true)
+            .with_pretty(false)
+            .with_global_labels(vec![Label::custom("team", "payments")])
+            .with_results_index(true)
+            .with_issue_url_template("https://jira.example.com/browse/{}")
+            .with_flaky_history_window(5)
+            .with_curl_attachments(true)
+            .with_uuid_fn(uuid::Uuid::new_v4)
+            .with_print_summary(false)
+            .build()
+            .unwrap();
+
+        let mut log = http_log("https://example.com/", None, "");
+        log.request
+            .headers
+            .insert("x-api-token", "secret".parse().unwrap());
+        let step = reporter.http_step(&log, &[]).unwrap();
+        assert_eq!(
+            parameter(&step, "request.header.x-api-token"),
+            Some("<masked>")
+        );
+
+        run_one(&mut reporter, "configured").await;
+        let [result] = sink.results().try_into().unwrap();
+        assert_eq!(result.test_case_id.as_deref().map(str::len), Some(64));
+        assert_eq!(result.description.as_deref(), Some("configured"));
+        assert!(result
+            .labels
+            .contains(&Label::Severity("minor".to_string())));
+        assert_eq!(label(&result, "team"), Some("payments"));
+        assert!(sink.0.lock().unwrap().contains_key("results-index.json"));
+    }
+
+    #[test]
+    fn build_rejects_an_invalid_pattern() {
+        let error = crate::AllureReporterBuilder::new(temp_dir().to_string_lossy())
+            .with_masked_header_pattern("x-(")
+            .build()
+            .err()
+            .unwrap();
+        assert!(format!("{error:#}").contains("invalid masked header pattern \"x-(\""));
+
+        let error = crate::AllureReporterBuilder::new(temp_dir().to_string_lossy())
+            .with_redacted_body_key_pattern("[")
+            .build()
+            .err()
+            .unwrap();
+        assert!(format!("{error:#}").contains("invalid redacted body key pattern"));
+    }
+
+    #[cfg(feature = "json-schema")]
+    #[test]
+    fn build_rejects_an_invalid_response_schema() {
+        let error = crate::AllureReporterBuilder::new(temp_dir().to_string_lossy())
+            .with_response_schema("/users/.*", serde_json::json!({ "type": "no-such-type" }))
+            .build()
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("/users/.*"), "{error}");
+    }
+
+    #[test]
+    fn build_rejects_options_that_cannot_be_combined() {
+        let error = crate::AllureReporterBuilder::new(temp_dir().to_string_lossy())
+            .with_shared_results_dir(true)
+            .with_clean(true)
+            .build()
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("with_clean"), "{error}");
+
+        let error = crate::AllureReporterBuilder::new(temp_dir().to_string_lossy())
+            .with_incremental_history(true)
+            .with_sink(MemorySink::default())
+            .build()
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("with_sink"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn captured_output_is_attached_to_its_test() {
+        let (reporter, sink) = reporter();
+        let mut reporter = reporter.attach_output(true);
+        let project = Arc::new(tanu_core::ProjectConfig {
+            name: "project".to_string(),
+            ..Default::default()
+        });
+        tanu_core::config::PROJECT
+            .scope(
+                project,
+                output::capture("module", "printed", async {
+                    crate::allure_println!("hello from the test");
+                }),
+            )
+            .await;
+        run_test(&mut reporter, "printed", Vec::new(), Ok(())).await;
+
+        let result = sink.results().remove(0);
+        assert_eq!(result.attachments.len(), 1);
+        let attachment = &result.attachments[0];
+        assert_eq!(attachment.name, "stdout");
+        assert_eq!(attachment.r#type, "text/plain");
+        let files = sink.0.lock().unwrap();
+        assert_eq!(files[&attachment.source], b"hello from the test\n");
+    }
+
+    #[tokio::test]
+    async fn a_history_dir_is_continued_and_rewritten_into_the_results_dir() {
+        let (previous, results_dir) = (temp_dir(), temp_dir());
+        let mut reporter = AllureReporter::with_results_dir(previous.to_string_lossy());
+        run_one(&mut reporter, "continued").await;
+
+        let mut reporter = AllureReporter::with_results_dir(results_dir.to_string_lossy())
+            .with_history_dir(previous.join("history"));
+        run_one(&mut reporter, "continued").await;
+
+        let history = read_json(&results_dir.join("history").join("history.json"));
+        let entries = history.as_object().unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = entries.values().next().unwrap();
+        assert_eq!(entry["items"].as_array().unwrap().len(), 2);
+        assert_eq!(entry["statistic"]["total"], 2);
+        let trend = read_json(&results_dir.join("history").join("duration-trend.json"));
+        assert_eq!(trend.as_array().unwrap().len(), 2);
+        for dir in [previous, results_dir] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn identical_calls_are_coalesced_into_one_counted_step() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.coalesce_http_calls(true);
+        let events = (0..3)
+            .map(|_| Event::Http(Box::new(http_log("https://example.com/poll", None, "{}"))))
+            .collect();
+        let result = map_test(&reporter, events, &test_case("polled", Ok(())));
+
+        assert_eq!(result.steps.len(), 1);
+        let step = &result.steps[0];
+        assert_eq!(parameter(step, "count"), Some("3"));
+        assert_eq!(step.attachments.len(), 3);
+    }
+
+    #[test]
+    fn calls_to_different_paths_are_not_coalesced() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.coalesce_http_calls(true);
+        let events = ["/a", "/b", "/a"]
+            .into_iter()
+            .map(|path| {
+                let url = format!("https://example.com{path}");
+                Event::Http(Box::new(http_log(&url, None, "{}")))
+            })
+            .collect();
+        let result = map_test(&reporter, events, &test_case("distinct", Ok(())));
+
+        assert_eq!(result.steps.len(), 3);
+        assert!(result
+            .steps
+            .iter()
+            .all(|step| parameter(step, "count").is_none()));
+    }
+
+    #[test]
+    fn a_coalesced_step_has_no_count_without_http_parameters() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.minimal().coalesce_http_calls(true);
+        let events = (0..2)
+            .map(|_| Event::Http(Box::new(http_log("https://example.com/poll", None, "{}"))))
+            .collect();
+        let result = map_test(&reporter, events, &test_case("minimal", Ok(())));
+
+        assert_eq!(result.steps.len(), 1);
+        assert!(result.steps[0].parameters.is_empty());
+        assert_eq!(result.steps[0].attachments.len(), 2);
+    }
+
+    #[test]
+    fn a_stdout_json_line_is_the_marker_and_one_json_object() {
+        let (reporter, _) = reporter();
+        let result = map_test(&reporter, Vec::new(), &test_case("printed", Ok(())));
+        let line = json_line(&result).unwrap();
+
+        let json = line.strip_prefix(STDOUT_JSON_MARKER).unwrap();
+        let json = json.strip_suffix('\n').unwrap();
+        assert!(!json.contains('\n'));
+        let parsed: TestResult = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.uuid, result.uuid);
+        assert_eq!(parsed.name, "printed");
+    }
+
+    #[test]
+    fn a_har_attachment_parses_with_every_call() {
+        let (reporter, sink) = reporter();
+        let reporter = reporter.attach_har(true).max_body_bytes(4);
+        let mut log = http_log(
+            "https://example.com/users?page=2",
+            Some("{\"name\":\"alice\"}"),
+            "[1,2,3]",
+        );
+        log.response.status = http::StatusCode::CREATED;
+        let events = vec![Event::Http(Box::new(log))];
+        let result = map_test(&reporter, events, &test_case("har", Ok(())));
+
+        let attachment = result
+            .attachments
+            .iter()
+            .find(|attachment| attachment.name == "requests.har")
+            .unwrap();
+        let har: serde_json::Value =
+            serde_json::from_slice(&sink.0.lock().unwrap()[&attachment.source]).unwrap();
+        let entries = har["log"]["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        let (request, response) = (&entries[0]["request"], &entries[0]["response"]);
+        assert_eq!(request["method"], "POST");
+        assert_eq!(request["httpVersion"], "unknown");
+        assert_eq!(request["queryString"][0]["name"], "page");
+        assert_eq!(request["bodySize"], 16);
+        assert_eq!(request["postData"]["text"], "{\"name\":\"alice\"}");
+        assert_eq!(response["status"], 201);
+        assert_eq!(response["statusText"], "Created");
+        assert_eq!(response["bodySize"], 7);
+        assert_eq!(response["content"]["size"], 7);
+        assert_eq!(response["content"]["text"], "[1,2");
+        assert_eq!(entries[0]["timings"]["dns"], -1);
+        assert_eq!(entries[0]["timings"]["wait"], 5.0);
+    }
+
+    /// A test with a blank name declared at `line`
+    fn unnamed_test(line: u32) -> Test {
+        let mut test = test_case("", Ok(()));
+        test.info = Arc::new(runner::TestInfo {
+            module: "module".to_string(),
+            name: " ".to_string(),
+            line,
+            ..Default::default()
+        });
+        test
+    }
+
+    #[test]
+    fn an_unnamed_test_is_reported_under_a_placeholder_and_its_line() {
+        let (reporter, _) = reporter();
+        let first = map_test(&reporter, Vec::new(), &unnamed_test(12));
+        let again = map_test(&reporter, Vec::new(), &unnamed_test(12));
+        let other = map_test(&reporter, Vec::new(), &unnamed_test(30));
+
+        assert_eq!(first.name, UNNAMED_TEST);
+        assert_eq!(
+            first.full_name.as_deref(),
+            Some("project::module#<unnamed test>@12")
+        );
+        assert_eq!(first.history_id, again.history_id);
+        assert_ne!(first.history_id, other.history_id);
+        // One warning per test, however often it is mapped
+        assert_eq!(reporter.warned_unnamed.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn interleaved_events_stay_with_their_own_tests() {
+        let (mut reporter, sink) = reporter();
+        for name in ["first", "second"] {
+            start(&mut reporter, name).await;
+        }
+        let check = |expr: &str| Event::Check(Box::new(runner::Check::success(expr)));
+        let call = |url: &str| Event::Http(Box::new(http_log(url, None, "")));
+        deliver(&mut reporter, "second", call("https://example.com/second")).await;
+        deliver(&mut reporter, "first", check("first check")).await;
+        deliver(&mut reporter, "second", check("second check")).await;
+        deliver(&mut reporter, "first", call("https://example.com/first")).await;
+        end(&mut reporter, "second", Ok(())).await;
+        end(&mut reporter, "first", Ok(())).await;
+
+        for result in sink.results() {
+            let steps: Vec<_> = result.steps.iter().map(|step| step.name.as_str()).collect();
+            match result.name.as_str() {
+                "first" => assert_eq!(steps, ["first check", "https://example.com/first"]),
+                "second" => assert_eq!(steps, ["https://example.com/second", "second check"]),
+                other => panic!("unexpected result {other}"),
+            }
+        }
+        assert_eq!(sink.results().len(), 2);
+    }
+
+    #[test]
+    fn a_run_summary_renders_as_one_line() {
+        let summary = RunSummary {
+            results: 12,
+            attachments: 30,
+            containers: 0,
+            retries: 1,
+            results_dir: PathBuf::from("/ci/allure-results"),
+        };
+        assert_eq!(
+            summary.to_line(),
+            "wrote 12 result(s), 30 attachment(s) and 0 container(s) to /ci/allure-results"
+        );
+    }
+
+    /// Records which method of the sink received each file
+    #[derive(Clone, Default)]
+    struct RoutingSink(Arc<Mutex<Vec<(&'static str, String)>>>);
+
+    impl ResultSink for RoutingSink {
+        fn write_file(&self, file_name: &str, _: &[u8]) -> io::Result<()> {
+            self.0.lock().unwrap().push(("file", file_name.to_string()));
+            Ok(())
+        }
+
+        fn write_result(&self, file_name: &str, _: &[u8]) -> io::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(("result", file_name.to_string()));
+            Ok(())
+        }
+
+        fn write_attachment(&self, file_name: &str, _: &[u8]) -> io::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(("attachment", file_name.to_string()));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn every_kind_of_file_goes_to_its_own_sink_method() {
+        let sink = RoutingSink::default();
+        let mut reporter = AllureReporter::with_results_dir(temp_dir().to_string_lossy())
+            .with_sink(sink.clone())
+            .with_default_categories();
+        let call = Event::Http(Box::new(http_log("https://example.com/", None, "{}")));
+        run_test(&mut reporter, "routed", vec![call], Ok(())).await;
+        reporter.on_summary(summary(1)).await.unwrap();
+
+        let written = sink.0.lock().unwrap();
+        for kind in ["file", "result", "attachment"] {
+            assert!(written.iter().any(|(k, _)| *k == kind), "no {kind} written");
+        }
+        for (kind, name) in written.iter() {
+            let expected = if name.ends_with("-result.json") {
+                "result"
+            } else if name.contains("-attachment") {
+                "attachment"
+            } else {
+                "file"
+            };
+            assert_eq!(*kind, expected, "{name}");
+        }
+    }
+
+    /// Fails to write any result, and on request to prepare, counting the attempted writes
+    #[derive(Clone, Default)]
+    struct FailingSink {
+        fail_prepare: bool,
+        writes: Arc<Mutex<usize>>,
+    }
+
+    impl ResultSink for FailingSink {
+        fn prepare(&self) -> io::Result<()> {
+            if self.fail_prepare {
+                return Err(io::Error::other("unreachable bucket"));
+            }
+            Ok(())
+        }
+
+        fn write_file(&self, _: &str, _: &[u8]) -> io::Result<()> {
+            *self.writes.lock().unwrap() += 1;
+            Ok(())
+        }
+
+        fn write_result(&self, _: &str, _: &[u8]) -> io::Result<()> {
+            *self.writes.lock().unwrap() += 1;
+            Err(io::Error::other("disk full"))
+        }
+    }
+
+    /// A result of `project::module#name` ready to be written
+    fn external_result(name: &str) -> TestResult {
+        let mut result = TestResult::new(name.to_string());
+        result.full_name = Some(format!("project::module#{name}"));
+        result.history_id = "history".to_string();
+        result
+    }
+
+    #[tokio::test]
+    async fn a_failed_write_names_the_test_and_its_uuid() {
+        let mut reporter = AllureReporter::with_results_dir(temp_dir().to_string_lossy())
+            .with_sink(FailingSink::default());
+        start(&mut reporter, "first").await;
+        let result = external_result("injected");
+        let uuid = result.uuid;
+
+        let error = format!("{:#}", reporter.add_external_result(result).unwrap_err());
+        assert!(error.contains("project::module#injected"), "{error}");
+        assert!(error.contains(&uuid.to_string()), "{error}");
+        assert!(error.contains("disk full"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn a_destination_that_cannot_be_prepared_stops_every_write() {
+        let sink = FailingSink {
+            fail_prepare: true,
+            ..Default::default()
+        };
+        let mut reporter =
+            AllureReporter::with_results_dir(temp_dir().to_string_lossy()).with_sink(sink.clone());
+        run_test(&mut reporter, "first", Vec::new(), Ok(())).await;
+        let error = reporter
+            .add_external_result(external_result("injected"))
+            .unwrap_err();
+        assert!(error.to_string().contains("unreachable bucket"), "{error}");
+        assert!(reporter.on_summary(summary(1)).await.is_err());
+
+        assert_eq!(*sink.writes.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn a_result_without_history_id_is_rejected_and_not_written() {
+        let (mut reporter, sink) = reporter();
+        let mut result = external_result("unidentified");
+        result.history_id = String::new();
+
+        let error = reporter.add_external_result(result).unwrap_err();
+        assert!(error.to_string().contains("empty history_id"), "{error}");
+        assert!(sink.results().is_empty());
+        assert_eq!(reporter.run_summary().results, 0);
+    }
+
+    #[tokio::test]
+    async fn a_finished_test_frees_its_buffer() {
+        let (mut reporter, _) = reporter();
+        start(&mut reporter, "buffered").await;
+        let check = Event::Check(Box::new(runner::Check::success("true")));
+        deliver(&mut reporter, "buffered", check).await;
+        assert_eq!(reporter.buffer.len(), 1);
+
+        end(&mut reporter, "buffered", Ok(())).await;
+        assert_eq!(reporter.buffer.len(), 0);
+    }
+
+    /// Reports `setup` and `teardown` as fixtures of their module
+    fn with_fixtures(reporter: AllureReporter) -> AllureReporter {
+        reporter.with_fixture_fn(|test| match test.info.name.as_str() {
+            "setup" => Some(FixtureKind::Before),
+            "teardown" => Some(FixtureKind::After),
+            _ => None,
+        })
+    }
+
+    #[tokio::test]
+    async fn fixtures_are_written_into_a_container_around_their_module() {
+        let (reporter, sink) = reporter();
+        let mut reporter = with_fixtures(reporter);
+        for name in ["setup", "wrapped", "teardown"] {
+            run_test(&mut reporter, name, Vec::new(), Ok(())).await;
+        }
+        reporter.on_summary(summary(1)).await.unwrap();
+
+        let [result] = sink.results().try_into().unwrap();
+        assert_eq!(result.name, "wrapped");
+        let [container] = sink.containers().try_into().unwrap();
+        assert_eq!(container.name.as_deref(), Some("project::module"));
+        assert_eq!(container.children, [result.uuid]);
+        let names = |fixtures: &[FixtureResult]| -> Vec<String> {
+            fixtures
+                .iter()
+                .map(|fixture| fixture.name.clone())
+                .collect()
+        };
+        assert_eq!(names(&container.befores), ["setup"]);
+        assert_eq!(names(&container.afters), ["teardown"]);
+    }
+
+    /// The `request.header.*` parameters of a request with the header `x-value: value`
+    fn header_parameters_of(value: &[u8], max_value_bytes: usize) -> Vec<Parameter> {
+        let mut headers = http::header::HeaderMap::new();
+        let value = http::header::HeaderValue::from_bytes(value).unwrap();
+        headers.insert("x-value", value);
+        let mut parameters = Vec::new();
+        push_header_parameters(
+            &mut parameters,
+            "request.header",
+            &headers,
+            &HeaderMasking::default(),
+            max_value_bytes,
+        );
+        parameters
+    }
+
+    #[test]
+    fn a_header_over_the_limit_is_truncated() {
+        let [parameter] = header_parameters_of(b"abcdefgh", 4).try_into().unwrap();
+        assert_eq!(parameter.name, "request.header.x-value");
+        assert_eq!(parameter.value, format!("abcd{TRUNCATED_SUFFIX}"));
+
+        let [parameter] = header_parameters_of(b"abcd", 4).try_into().unwrap();
+        assert_eq!(parameter.value, "abcd");
+    }
+
+    #[test]
+    fn a_header_is_truncated_at_a_character_boundary() {
+        // "é" takes two bytes, so a limit of 2 would split it
+        let [parameter] = header_parameters_of("aéb".as_bytes(), 2)
+            .try_into()
+            .unwrap();
+        assert_eq!(parameter.value, format!("a{TRUNCATED_SUFFIX}"));
+    }
+
+    #[test]
+    fn a_header_value_that_is_not_utf8_is_shown_as_hex() {
+        let [parameter] = header_parameters_of(b"ok\xff\xfe", 256).try_into().unwrap();
+        assert_eq!(parameter.value, "<non-UTF-8, hex: 6f6bfffe>");
+    }
+
+    #[test]
+    fn identical_bodies_share_one_attachment_file() {
+        let (reporter, sink) = reporter();
+        let events = ["/first", "/second"]
+            .into_iter()
+            .map(|path| {
+                let url = format!("https://example.com{path}");
+                Event::Http(Box::new(http_log(&url, None, "{\"id\":1}")))
+            })
+            .collect();
+        let result = map_test(&reporter, events, &test_case("shared", Ok(())));
+
+        let sources: Vec<_> = result
+            .steps
+            .iter()
+            .map(|step| step.attachments[0].source.as_str())
+            .collect();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0], sources[1]);
+        assert_eq!(sink.attachment_files(), [sources[0]]);
+        assert_eq!(sink.file(sources[0]), b"{\"id\":1}");
+    }
+
+    #[test]
+    fn a_gzipped_body_decompresses_to_the_original() {
+        use std::io::Read as _;
+
+        let (reporter, sink) = reporter();
+        let reporter = reporter.with_attachment_compression(64);
+        let body = "{\"items\":[".to_string() + &"1,".repeat(100) + "1]}";
+        let attachment = reporter
+            .write_body_attachment("response body", "application/json", body.as_bytes())
+            .unwrap();
+
+        assert!(attachment.source.ends_with("-attachment.gz"));
+        assert_eq!(attachment.r#type, "application/gzip");
+        let compressed = sink.file(&attachment.source);
+        assert!(compressed.len() < body.len());
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, body);
+
+        let small = reporter
+            .write_body_attachment("response body", "application/json", b"{}")
+            .unwrap();
+        assert_eq!(small.r#type, "application/json");
+        assert_eq!(sink.file(&small.source), b"{}");
+    }
+
+    fn json_headers() -> http::header::HeaderMap {
+        let mut headers = http::header::HeaderMap::new();
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            "application/json".parse().unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn sensitive_json_keys_are_redacted_at_any_depth() {
+        let (mut reporter, _) = reporter();
+        reporter.add_redacted_body_key("Password");
+        let body = r#"{"user":{"name":"alice","password":"hunter2"},"tokens":[{"PASSWORD":"x"}]}"#;
+
+        let redacted = reporter.redact_body(&json_headers(), body);
+        let redacted: serde_json::Value = serde_json::from_str(&redacted).unwrap();
+        assert_eq!(
+            redacted,
+            serde_json::json!({
+                "user": { "name": "alice", "password": "***" },
+                "tokens": [{ "PASSWORD": "***" }],
+            })
+        );
+    }
+
+    #[test]
+    fn bodies_that_are_not_json_are_not_redacted() {
+        let (mut reporter, _) = reporter();
+        reporter.add_redacted_body_key("password");
+        let form = "password=hunter2";
+        let mut headers = http::header::HeaderMap::new();
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded".parse().unwrap(),
+        );
+        assert!(matches!(reporter.redact_body(&headers, form), Cow::Borrowed(b) if b == form));
+
+        let malformed = r#"{"password": "hunter2""#;
+        let redacted = reporter.redact_body(&json_headers(), malformed);
+        assert!(matches!(redacted, Cow::Borrowed(b) if b == malformed));
+    }
+
+    #[test]
+    fn small_attachments_are_inlined_as_data_uris() {
+        let (reporter, sink) = reporter();
+        let reporter = reporter.with_inline_attachments(8);
+
+        let inlined = reporter
+            .write_attachment("small", "text/plain", b"hello")
+            .unwrap();
+        assert_eq!(inlined.source, "data:text/plain;base64,aGVsbG8=");
+        assert_eq!(inlined.r#type, "text/plain");
+
+        let written = reporter
+            .write_attachment("large", "text/plain", b"hello, world")
+            .unwrap();
+        assert!(written.source.ends_with("-attachment"));
+        assert_eq!(sink.attachment_files(), [written.source]);
+    }
+
+    #[test]
+    fn undeclared_bodies_are_sniffed_by_their_content() {
+        assert_eq!(sniff_media_type(" {\"id\": 1}"), Some("application/json"));
+        assert_eq!(sniff_media_type("[1, 2]"), Some("application/json"));
+        assert_eq!(sniff_media_type("{not json"), None);
+        assert_eq!(sniff_media_type("<!DOCTYPE html><p>"), Some("text/html"));
+        assert_eq!(sniff_media_type("<html><body>"), Some("text/html"));
+        assert_eq!(
+            sniff_media_type("<?xml version=\"1.0\"?>"),
+            Some("application/xml")
+        );
+        assert_eq!(sniff_media_type("plain text"), None);
+
+        let (reporter, _) = reporter();
+        assert_eq!(
+            reporter.body_media_type(None, "{}"),
+            Some("application/json")
+        );
+        assert_eq!(
+            reporter.body_media_type(Some("application/octet-stream"), "<html>"),
+            Some("text/html")
+        );
+        // A declared type is trusted
+        assert_eq!(
+            reporter.body_media_type(Some("text/plain"), "{}"),
+            Some("text/plain")
+        );
+    }
+
+    #[test]
+    fn media_type_overrides_replace_declared_and_sniffed_types() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.with_media_type_overrides(HashMap::from([
+            (
+                "Application/Problem+JSON".to_string(),
+                "application/json".to_string(),
+            ),
+            (
+                "application/octet-stream".to_string(),
+                "image/png".to_string(),
+            ),
+        ]));
+        assert_eq!(
+            reporter.body_media_type(Some("application/problem+json"), "{}"),
+            Some("application/json")
+        );
+        assert_eq!(reporter.body_media_type(None, "{}"), Some("image/png"));
+    }
+
+    #[test]
+    fn a_curl_command_quotes_the_body_and_masks_headers() {
+        let mut log = http_log("https://example.com/notes?q=a b", Some("it's done"), "");
+        log.request.headers.insert(
+            http::header::AUTHORIZATION,
+            "Bearer secret".parse().unwrap(),
+        );
+        let command = curl_command(
+            &log.request,
+            log.request.body.as_deref(),
+            &HeaderMasking::default(),
+        );
+
+        assert!(
+            command.starts_with("curl -X POST 'https://example.com/notes?q=a%20b'"),
+            "{command}"
+        );
+        assert!(
+            command.contains("-H 'authorization: <masked>'"),
+            "{command}"
+        );
+        assert!(!command.contains("secret"), "{command}");
+        assert!(command.ends_with(r"--data-raw 'it'\''s done'"), "{command}");
+    }
+
+    #[test]
+    fn query_pairs_become_parameters_with_secrets_masked() {
+        let (reporter, _) = reporter();
+        let log = http_log(
+            "https://example.com/search?q=rust%20lang&page=2&API_KEY=k",
+            None,
+            "",
+        );
+        let step = reporter.http_step(&log, &[]).unwrap();
+
+        assert_eq!(parameter(&step, "request.query.q"), Some("rust lang"));
+        assert_eq!(parameter(&step, "request.query.page"), Some("2"));
+        assert_eq!(parameter(&step, "request.query.API_KEY"), Some("<masked>"));
+        let masked = step
+            .parameters
+            .iter()
+            .find(|parameter| parameter.name == "request.query.API_KEY")
+            .unwrap();
+        assert!(matches!(masked.mode, Some(ParameterMode::Masked)));
+    }
+
+    #[test]
+    fn method_and_status_become_parameters() {
+        let (reporter, _) = reporter();
+        let mut log = http_log("https://example.com/missing", None, "");
+        log.request.method = http::Method::DELETE;
+        log.response.status = http::StatusCode::NOT_FOUND;
+        let step = reporter.http_step(&log, &[]).unwrap();
+        assert_eq!(parameter(&step, "request.method"), Some("DELETE"));
+        assert_eq!(parameter(&step, "response.status"), Some("404 Not Found"));
+
+        // A code without a canonical reason is shown on its own
+        log.response.status = http::StatusCode::from_u16(599).unwrap();
+        let step = reporter.http_step(&log, &[]).unwrap();
+        assert_eq!(parameter(&step, "response.status"), Some("599"));
+    }
+
+    #[test]
+    fn request_and_response_bodies_are_attached_to_their_step() {
+        let (reporter, sink) = reporter();
+        let mut log = http_log(
+            "https://example.com/users",
+            Some("{\"name\":\"alice\"}"),
+            "{\"id\":7}",
+        );
+        log.request.headers = json_headers();
+        let step = reporter.http_step(&log, &[]).unwrap();
+
+        let attachments: Vec<_> = step
+            .attachments
+            .iter()
+            .map(|attachment| (attachment.name.as_str(), attachment.r#type.as_str()))
+            .collect();
+        assert_eq!(
+            attachments,
+            [
+                ("request body", "application/json"),
+                ("response body", "application/json"),
+            ]
+        );
+        assert_eq!(
+            sink.file(&step.attachments[0].source),
+            b"{\"name\":\"alice\"}"
+        );
+        assert_eq!(sink.file(&step.attachments[1].source), b"{\"id\":7}");
+    }
 
-        // Generate deterministic history_id based on test identity
-        let history_id = generate_history_id(project, module, test_name, &parameters);
+    #[test]
+    fn a_body_over_the_limit_is_attached_truncated() {
+        let (reporter, sink) = reporter();
+        let reporter = reporter.max_body_bytes(1024);
+        let body = "x".repeat(1024 * 1024);
+        let step = reporter
+            .http_step(&http_log("https://example.com/large", None, &body), &[])
+            .unwrap();
 
-        TestResult {
-            uuid: uuid::Uuid::new_v4(),
-            history_id,
-            test_case_id: Default::default(),
-            name: test_name.to_string(),
-            full_name: Some(format!("{project}::{module}::{test_name}")),
-            description: Default::default(),
-            description_html: Default::default(),
-            links: Default::default(),
-            labels: vec![
-                Label::ParentSuite(project.to_string()),
-                Label::Suite(module.to_string()),
-                Label::Host(
-                    hostname::get()
-                        .map(|h| h.to_string_lossy().into_owned())
-                        .unwrap_or_else(|_| "unknown".to_string()),
+        assert_eq!(step.attachments.len(), 1);
+        let attachment = &step.attachments[0];
+        assert_eq!(sink.file(&attachment.source), &body.as_bytes()[..1024]);
+        // The parameter still reports the size received
+        assert_eq!(parameter(&step, "response.bytes"), Some("1048576"));
+    }
+
+    #[test]
+    fn bodies_of_skipped_types_are_replaced_by_a_parameter() {
+        let (reporter, sink) = reporter();
+        let mut log = http_log("https://example.com/clip", Some("{}"), "binary");
+        log.response
+            .headers
+            .insert(http::header::CONTENT_TYPE, "Video/MP4".parse().unwrap());
+        log.request.headers = json_headers();
+        let step = reporter.http_step(&log, &[]).unwrap();
+
+        assert_eq!(
+            parameter(&step, "response.body"),
+            Some("<skipped: Video/MP4>")
+        );
+        assert_eq!(parameter(&step, "request.body"), None);
+        let [attachment] = step.attachments.try_into().unwrap();
+        assert_eq!(attachment.name, "request body");
+        assert_eq!(sink.attachment_files().len(), 1);
+    }
+
+    #[test]
+    fn the_timeline_lists_every_call_in_order() {
+        let (reporter, sink) = reporter();
+        let reporter = reporter.attach_timeline(true);
+        let start = SystemTime::UNIX_EPOCH + Duration::from_millis(1_000);
+        let call = |url: &str, offset: u64| {
+            let mut log = http_log(url, None, "");
+            log.started_at = start + Duration::from_millis(offset);
+            log.ended_at = log.started_at + Duration::from_millis(5);
+            Event::Http(Box::new(log))
+        };
+        let check = Event::Check(Box::new(runner::Check::success("true")));
+        let events = vec![
+            call("https://example.com/a", 0),
+            check,
+            call("https://example.com/b", 10),
+        ];
+        let result = map_test(&reporter, events, &test_case("timed", Ok(())));
+
+        let attachment = result
+            .attachments
+            .iter()
+            .find(|attachment| attachment.name == "timeline")
+            .unwrap();
+        let timeline: serde_json::Value =
+            serde_json::from_slice(&sink.file(&attachment.source)).unwrap();
+        assert_eq!(
+            timeline,
+            serde_json::json!([
+                { "name": "POST https://example.com/a", "start": 1_000, "stop": 1_005 },
+                { "name": "POST https://example.com/b", "start": 1_010, "stop": 1_015 },
+            ])
+        );
+    }
+
+    #[cfg(feature = "json-schema")]
+    #[test]
+    fn a_response_violating_its_schema_gets_a_failed_sub_step() {
+        let (mut reporter, sink) = reporter();
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["id"],
+            "properties": { "id": { "type": "integer" } },
+        });
+        reporter.add_response_schema("/users/.*", &schema).unwrap();
+
+        let valid = reporter
+            .http_step(
+                &http_log("https://example.com/users/1", None, "{\"id\":1}"),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(valid.steps[0].name, "response matches schema");
+        assert_eq!(valid.steps[0].status, Status::Passed);
+
+        let invalid = reporter
+            .http_step(
+                &http_log("https://example.com/users/2", None, "{\"id\":\"2\"}"),
+                &[],
+            )
+            .unwrap();
+        let [sub_step] = invalid.steps.try_into().unwrap();
+        assert_eq!(sub_step.status, Status::Failed);
+        let violations = sink.file(&sub_step.attachments[0].source);
+        assert!(String::from_utf8(violations).unwrap().starts_with("/id: "));
+
+        let unmatched = reporter
+            .http_step(&http_log("https://example.com/orders", None, "[]"), &[])
+            .unwrap();
+        assert!(unmatched.steps.is_empty());
+    }
+
+    #[test]
+    fn every_distinct_host_becomes_a_service_label() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.service_labels(true);
+        let events = [
+            "https://users.example.com/1",
+            "https://orders.example.com/1",
+            "https://users.example.com/2",
+        ]
+        .into_iter()
+        .map(|url| Event::Http(Box::new(http_log(url, None, ""))))
+        .collect();
+        let result = map_test(&reporter, events, &test_case("services", Ok(())));
+
+        let services: Vec<_> = result
+            .labels
+            .iter()
+            .filter_map(|label| match label {
+                Label::Custom { name, value } if name == "service" => Some(value.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(services, ["users.example.com", "orders.example.com"]);
+    }
+
+    #[test]
+    fn calls_and_their_checks_are_grouped_by_method_and_host() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.with_step_grouping(true);
+        let call = |url: &str| Event::Http(Box::new(http_log(url, None, "")));
+        let check = |expr: &str, passed: bool| {
+            let check = if passed {
+                runner::Check::success(expr)
+            } else {
+                runner::Check::error(expr)
+            };
+            Event::Check(Box::new(check))
+        };
+        let events = vec![
+            check("setup", true),
+            call("https://users.example.com/1"),
+            check("found", false),
+            call("https://users.example.com/2"),
+            call("https://orders.example.com/1"),
+        ];
+        let result = map_test(&reporter, events, &test_case("grouped", Ok(())));
+
+        let tree: Vec<(&str, Status, Vec<&str>)> = result
+            .steps
+            .iter()
+            .map(|step| {
+                let children = step.steps.iter().map(|child| child.name.as_str()).collect();
+                (step.name.as_str(), step.status.clone(), children)
+            })
+            .collect();
+        assert_eq!(
+            tree,
+            [
+                ("setup", Status::Passed, vec![]),
+                (
+                    "POST users.example.com",
+                    Status::Failed,
+                    vec![
+                        "https://users.example.com/1",
+                        "found",
+                        "https://users.example.com/2"
+                    ]
                 ),
-                Label::Thread(test.worker_id.to_string()),
-            ],
-            parameters,
-            attachments: Default::default(),
-            status,
-            status_details,
-            stage: Some(Stage::Finished),
-            start: Some(system_time_to_unix_millis(test.started_at)),
-            stop: Some(system_time_to_unix_millis(test.ended_at)),
-            steps,
+                (
+                    "POST orders.example.com",
+                    Status::Passed,
+                    vec!["https://orders.example.com/1"]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_failed_check_carries_its_expression_in_its_status_details() {
+        let (reporter, _) = reporter();
+        let checks = vec![
+            Event::Check(Box::new(runner::Check::success("check!(true)"))),
+            Event::Check(Box::new(runner::Check::error("check!(status == 200)"))),
+        ];
+        let result = map_test(&reporter, checks, &test_case("checked", failed()));
+
+        let [passed, failed] = &result.steps[..] else {
+            panic!("expected two steps, got {:?}", result.steps);
+        };
+        assert_eq!(passed.status, Status::Passed);
+        assert!(passed.status_details.is_none());
+        assert_eq!(failed.name, "check!(status == 200)");
+        assert_eq!(failed.status, Status::Failed);
+        let details = failed.status_details.as_ref().unwrap();
+        assert_eq!(details.message.as_deref(), Some("check!(status == 200)"));
+        assert_eq!(details.trace, None);
+    }
+
+    #[test]
+    fn a_panic_keeps_its_location_and_backtrace_as_the_trace() {
+        let (reporter, _) = reporter();
+        let report = "\u{1b}[31mattempt to divide by zero\u{1b}[0m\n\nLocation:\n    tests/math.rs:7:5\n\nStack backtrace:\n   0: math::divide";
+        let panicked = Err(runner::Error::Panicked(report.to_string()));
+        let result = map_test(&reporter, Vec::new(), &test_case("divides", panicked));
+
+        assert_eq!(result.status, Status::Broken);
+        let details = result.status_details.unwrap();
+        let message = details.message.unwrap();
+        assert!(message.ends_with("attempt to divide by zero"), "{message}");
+        assert!(!message.contains('\u{1b}'), "{message}");
+        let trace = details.trace.unwrap();
+        assert!(trace.starts_with("Location:"), "{trace}");
+        assert!(trace.contains("tests/math.rs:7:5"), "{trace}");
+        assert!(trace.contains("0: math::divide"), "{trace}");
+    }
+
+    #[test]
+    fn a_failed_check_attaches_the_values_it_captured() {
+        let (reporter, sink) = reporter();
+        let checks = vec![
+            Event::Check(Box::new(runner::Check::success(
+                "check!(a < b)\n  a = 1\n  b = 2",
+            ))),
+            Event::Check(Box::new(runner::Check::error(
+                "check!(a > b)\n  a = 1\n  b = 2",
+            ))),
+        ];
+        let result = map_test(&reporter, checks, &test_case("compared", failed()));
+
+        assert!(result.steps[0].attachments.is_empty());
+        let step = &result.steps[1];
+        assert_eq!(step.name, "check!(a > b)");
+        let [attachment] = &step.attachments[..] else {
+            panic!("expected one attachment, got {}", step.attachments.len());
+        };
+        assert_eq!(attachment.name, "captured values");
+        assert_eq!(attachment.r#type, "text/plain");
+        assert_eq!(sink.file(&attachment.source), b"a = 1\n  b = 2");
+    }
+
+    #[test]
+    fn a_failed_equality_check_attaches_its_diff() {
+        let (reporter, sink) = reporter();
+        let expr = "check_eq!(status, 200): assertion failed: `(left == right)`\n\nDiff < left / right > :\n<404\n>200";
+        let check = Event::Check(Box::new(runner::Check::error(expr)));
+        let result = map_test(&reporter, vec![check], &test_case("equal", failed()));
+
+        let step = &result.steps[0];
+        assert_eq!(
+            step.name,
+            "check_eq!(status, 200): assertion failed: `(left == right)`"
+        );
+        let [attachment] = &step.attachments[..] else {
+            panic!("expected one attachment, got {}", step.attachments.len());
+        };
+        assert_eq!(attachment.name, "assertion diff");
+        let diff = String::from_utf8(sink.file(&attachment.source)).unwrap();
+        assert!(diff.starts_with("Diff < left / right > :"), "{diff}");
+        assert!(diff.ends_with("<404\n>200"), "{diff}");
+    }
+
+    #[test]
+    fn informational_responses_take_the_configured_status() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.informational_status(Status::Skipped);
+        let mut log = http_log("https://example.com/upgrade", None, "");
+        log.response.status = http::StatusCode::SWITCHING_PROTOCOLS;
+        assert_eq!(
+            reporter.http_step(&log, &[]).unwrap().status,
+            Status::Skipped
+        );
+
+        log.response.status = http::StatusCode::OK;
+        assert_eq!(
+            reporter.http_step(&log, &[]).unwrap().status,
+            Status::Passed
+        );
+    }
+
+    #[test]
+    fn an_error_mapper_replaces_the_default_statuses() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.with_error_mapper(|error| match error {
+            runner::Error::Panicked(_) => Status::Failed,
+            _ => Status::Skipped,
+        });
+        let panicked = Err(runner::Error::Panicked("boom".to_string()));
+        for (result, status) in [
+            (Ok(()), Status::Passed),
+            (failed(), Status::Skipped),
+            (panicked, Status::Failed),
+        ] {
+            let result = map_test(&reporter, Vec::new(), &test_case("mapped", result));
+            assert_eq!(result.status, status);
         }
     }
-}
 
-#[async_trait::async_trait]
-impl Reporter for AllureReporter {
-    async fn on_check(
-        &mut self,
-        project_name: String,
-        module_name: String,
-        test_name: String,
-        check: Box<runner::Check>,
-    ) -> eyre::Result<()> {
-        self.buffer
-            .entry((project_name, module_name, test_name))
-            .or_default()
-            .events
-            .push(Event::Check(check));
-        Ok(())
+    #[test]
+    fn an_expected_error_status_passes_its_call() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.with_tags_fn(|_| vec!["expect-status:404".to_string()]);
+        let mut missing = http_log("https://example.com/missing", None, "");
+        missing.response.status = http::StatusCode::NOT_FOUND;
+        let mut broken = http_log("https://example.com/broken", None, "");
+        broken.response.status = http::StatusCode::INTERNAL_SERVER_ERROR;
+        let events = vec![
+            Event::Http(Box::new(missing)),
+            Event::Http(Box::new(broken)),
+        ];
+        let result = map_test(&reporter, events, &test_case("negative", Ok(())));
+
+        let expected = &result.steps[0];
+        assert_eq!(expected.status, Status::Passed);
+        let message = expected.status_details.as_ref().unwrap().message.as_deref();
+        assert_eq!(message, Some("expected 404"));
+        assert_eq!(result.steps[1].status, Status::Failed);
+        assert!(result.steps[1].status_details.is_none());
     }
 
-    async fn on_call(
-        &mut self,
-        project_name: String,
-        module_name: String,
-        test_name: String,
-        log: runner::CallLog,
-    ) -> eyre::Result<()> {
-        if let runner::CallLog::Http(http_log) = log {
-            self.buffer
-                .entry((project_name, module_name, test_name))
-                .or_default()
-                .events
-                .push(Event::Http(http_log));
+    #[tokio::test]
+    async fn muted_and_known_issue_tags_mark_their_results() {
+        let (reporter, sink) = reporter();
+        let mut reporter = reporter.with_tags_fn(|test| match test.info.name.as_str() {
+            "muted" => vec!["muted".to_string()],
+            "known" => vec!["known_issue:https://jira.example.com/browse/API-1".to_string()],
+            _ => Vec::new(),
+        });
+        for name in ["muted", "known"] {
+            run_test(&mut reporter, name, Vec::new(), failed()).await;
         }
-        Ok(())
+        reporter.on_summary(summary(2)).await.unwrap();
+
+        let results = sink.results();
+        let result = |name: &str| results.iter().find(|r| r.name == name).unwrap();
+        let muted = result("muted").status_details.as_ref().unwrap();
+        assert_eq!((muted.muted, muted.known), (Some(true), None));
+        let known = result("known");
+        let details = known.status_details.as_ref().unwrap();
+        assert_eq!((details.muted, details.known), (None, Some(true)));
+        assert_eq!(known.links[0].r#type, "issue");
+        assert_eq!(
+            known.links[0].url.as_str(),
+            "https://jira.example.com/browse/API-1"
+        );
+
+        // A muted run stays in the history but out of its statistic
+        let history: History = serde_json::from_slice(&sink.file("history/history.json")).unwrap();
+        let muted = &history[&result("muted").history_id];
+        assert_eq!(muted.items.len(), 1);
+        assert_eq!(muted.statistic.total, 0);
+        let known = &history[&known.history_id];
+        assert_eq!((known.statistic.failed, known.statistic.total), (1, 1));
     }
 
-    async fn on_end(
-        &mut self,
-        project: String,
-        module: String,
-        test_name: String,
-        test: Test,
-    ) -> eyre::Result<()> {
-        self.ensure_results_dir()?;
+    #[test]
+    fn alternating_statuses_in_the_history_mark_a_result_flaky() {
+        let (reporter, _) = reporter();
+        let mut reporter = reporter.flaky_history_window(2);
+        let test = test_case("alternating", Ok(()));
+        let history_id = map_test(&reporter, Vec::new(), &test).history_id;
+        let record = |reporter: &mut AllureReporter, status| {
+            let run = RunResult {
+                history_id: history_id.clone(),
+                status,
+                status_details: None,
+                muted: false,
+                start: 0,
+                stop: 0,
+                uuid: uuid::Uuid::new_v4(),
+                full_name: "project::module#alternating".to_string(),
+            };
+            record_history(&mut reporter.history, &run);
+        };
 
-        let buffer = self
-            .buffer
-            .get(&(project.clone(), module.clone(), test_name.clone()))
-            .ok_or_else(|| eyre::eyre!("test case \"{test_name}\" not found in the buffer"))?;
+        // The failure is older than the two runs the window looks at
+        for status in [Status::Failed, Status::Passed, Status::Passed] {
+            record(&mut reporter, status);
+        }
+        let result = map_test(&reporter, Vec::new(), &test);
+        assert!(
+            result.status_details.is_none(),
+            "{:?}",
+            result.status_details
+        );
 
-        let test_result =
-            self.map_to_allure_test_result(&project, &module, &test_name, &buffer.events, &test);
+        record(&mut reporter, Status::Broken);
+        let result = map_test(&reporter, Vec::new(), &test);
+        assert_eq!(result.status_details.unwrap().flaky, Some(true));
+    }
 
-        let file_name = format!("{}-result.json", test_result.uuid);
-        let file_path = Path::new(&self.results_dir).join(file_name);
+    #[test]
+    fn the_environment_is_the_origin_of_the_first_call() {
+        let (reporter, _) = reporter();
+        let events = || {
+            vec![
+                Event::Check(Box::new(runner::Check::success("true"))),
+                Event::Http(Box::new(http_log(
+                    "https://staging.example.com:8443/a",
+                    None,
+                    "",
+                ))),
+                Event::Http(Box::new(http_log("https://other.example.com/b", None, ""))),
+            ]
+        };
+        let test = test_case("targeted", Ok(()));
+        let unlabeled = map_test(&reporter, events(), &test);
+        assert_eq!(label(&unlabeled, "environment"), None);
 
-        let json = serde_json::to_string_pretty(&test_result)?;
+        let reporter = reporter.environment_label(true);
+        let result = map_test(&reporter, events(), &test);
+        let origin = "https://staging.example.com:8443";
+        assert_eq!(label(&result, "environment"), Some(origin));
+        let parameter = |result: &TestResult| {
+            let found = result.parameters.iter().find(|p| p.name == "Environment");
+            found.map(|p| (p.value.clone(), p.excluded))
+        };
+        assert_eq!(parameter(&result), Some((origin.to_string(), Some(true))));
+        assert_eq!(result.history_id, unlabeled.history_id);
 
-        fs::write(file_path, json)?;
+        let reporter = reporter.environment_in_history_id(true);
+        let result = map_test(&reporter, events(), &test);
+        assert_eq!(parameter(&result), Some((origin.to_string(), Some(false))));
+        assert_ne!(result.history_id, unlabeled.history_id);
+    }
 
-        // Track result for history update
-        self.current_run_results.push(RunResult {
-            history_id: test_result.history_id.clone(),
-            status: test_result.status.clone(),
-            status_details: test_result
-                .status_details
-                .as_ref()
-                .and_then(|d| d.message.clone()),
-            start: test_result.start.unwrap_or(0),
-            stop: test_result.stop.unwrap_or(0),
-            uuid: test_result.uuid.to_string(),
+    #[tokio::test]
+    async fn tests_are_numbered_in_the_order_they_started() {
+        let (reporter, sink) = reporter();
+        let mut reporter = reporter.start_order_parameter(true);
+        start(&mut reporter, "first").await;
+        start(&mut reporter, "second").await;
+        end(&mut reporter, "second", Ok(())).await;
+        end(&mut reporter, "first", Ok(())).await;
+
+        let (unnumbered, _) = self::reporter();
+        let results = sink.results();
+        assert_eq!(results.len(), 2);
+        for result in results {
+            let order = result
+                .parameters
+                .iter()
+                .find(|parameter| parameter.name == "start_order")
+                .unwrap();
+            let expected = if result.name == "first" { "1" } else { "2" };
+            assert_eq!(order.value, expected, "{}", result.name);
+            assert_eq!(order.excluded, Some(true));
+
+            let test = test_case(&result.name, Ok(()));
+            let history_id = map_test(&unnumbered, Vec::new(), &test).history_id;
+            assert_eq!(result.history_id, history_id);
+        }
+    }
+
+    #[test]
+    fn the_version_label_names_this_crates_version() {
+        let (reporter, _) = reporter();
+        let test = test_case("versioned", Ok(()));
+        let result = map_test(&reporter, Vec::new(), &test);
+        assert_eq!(label(&result, "tanu_allure_version"), None);
+
+        let result = map_test(&reporter.version_label(true), Vec::new(), &test);
+        let version = label(&result, "tanu_allure_version");
+        assert_eq!(version, Some(env!("CARGO_PKG_VERSION")));
+    }
+
+    #[tokio::test]
+    async fn an_external_result_is_written_and_recorded_like_a_tanu_one() {
+        let (mut reporter, sink) = reporter();
+        run_test(&mut reporter, "native", Vec::new(), Ok(())).await;
+        let mut external = external_result("converted");
+        external.status = Status::Failed;
+        let uuid = external.uuid;
+        reporter.add_external_result(external).unwrap();
+        reporter.on_summary(summary(1)).await.unwrap();
+
+        let written = sink.file(&format!("{uuid}-result.json"));
+        let written: TestResult = serde_json::from_slice(&written).unwrap();
+        assert_eq!(written.name, "converted");
+        assert_eq!(written.status, Status::Failed);
+        assert_eq!(sink.results().len(), 2);
+        assert_eq!(reporter.run_summary().results, 2);
+
+        let history: History = serde_json::from_slice(&sink.file("history/history.json")).unwrap();
+        let entry = &history["history"];
+        assert_eq!((entry.statistic.failed, entry.statistic.total), (1, 1));
+        assert_eq!(entry.items[0].uid, uuid.to_string());
+    }
+
+    #[test]
+    fn set_history_id_matches_the_id_the_reporter_writes() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.start_order_parameter(true);
+        let written = map_test(&reporter, Vec::new(), &test_case("identified", Ok(())));
+
+        let mut external = TestResult::new(written.name.clone());
+        external.parameters = written.parameters;
+        external.set_history_id("project", "module");
+        assert_eq!(external.history_id, written.history_id);
+    }
+
+    #[tokio::test]
+    async fn environment_properties_escape_keys_and_values() {
+        let (mut reporter, sink) = reporter();
+        reporter.add_environment("db=host:port", "localhost:5432");
+        reporter.add_environment("motd", "line one\nC:\\line two");
+        run_one(&mut reporter, "configured").await;
+
+        let properties = String::from_utf8(sink.file("environment.properties")).unwrap();
+        let lines: Vec<_> = properties.lines().collect();
+        assert!(
+            lines.contains(&"db\\=host\\:port = localhost:5432"),
+            "{properties}"
+        );
+        assert!(
+            lines.contains(&"motd = line one\\nC:\\\\line two"),
+            "{properties}"
+        );
+        let os = format!("os_platform = {}", std::env::consts::OS);
+        assert!(lines.contains(&os.as_str()), "{properties}");
+        let mut sorted = lines.clone();
+        sorted.sort();
+        assert_eq!(lines, sorted);
+    }
+
+    #[tokio::test]
+    async fn categories_json_is_written_only_when_categories_are_set() {
+        let (mut reporter, sink) = reporter();
+        run_one(&mut reporter, "uncategorized").await;
+        assert!(!sink.0.lock().unwrap().contains_key("categories.json"));
+
+        let (reporter, sink) = self::reporter();
+        let mut reporter = reporter.with_default_categories();
+        run_one(&mut reporter, "categorized").await;
+        let categories: serde_json::Value =
+            serde_json::from_slice(&sink.file("categories.json")).unwrap();
+        assert_eq!(
+            categories,
+            serde_json::json!([
+                {"name": "Product defects", "matchedStatuses": ["failed"]},
+                {"name": "Test defects", "matchedStatuses": ["broken"]},
+            ])
+        );
+    }
+
+    /// The severity labels of `result`
+    fn severities(result: &TestResult) -> Vec<&str> {
+        result
+            .labels
+            .iter()
+            .filter_map(|label| match label {
+                Label::Severity(severity) => Some(severity.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_severity_hook_labels_results_and_falls_back_to_normal() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.with_severity_fn(|test| match test.info.name.as_str() {
+            "login" => Some("critical".to_string()),
+            "typo" => Some("urgent".to_string()),
+            _ => None,
         });
+        for (name, expected) in [
+            ("login", vec!["critical"]),
+            ("typo", vec!["normal"]),
+            ("other", vec![]),
+        ] {
+            let result = map_test(&reporter, Vec::new(), &test_case(name, Ok(())));
+            assert_eq!(severities(&result), expected, "{name}");
+        }
+        assert_eq!(known_severity("blocker", "test"), "blocker");
+    }
 
-        Ok(())
+    #[test]
+    fn the_hook_beats_a_severity_tag_which_beats_the_default() {
+        let (reporter, _) = reporter();
+        let reporter = reporter
+            .with_default_severity("minor")
+            .with_tags_fn(|test| match test.info.name.as_str() {
+                "untagged" => Vec::new(),
+                _ => vec![
+                    "severity:trivial".to_string(),
+                    "severity:blocker".to_string(),
+                ],
+            })
+            .with_severity_fn(|test| (test.info.name == "hooked").then(|| "critical".to_string()));
+        for (name, expected) in [
+            ("hooked", "critical"),
+            ("tagged", "trivial"),
+            ("untagged", "minor"),
+        ] {
+            let result = map_test(&reporter, Vec::new(), &test_case(name, Ok(())));
+            assert_eq!(severities(&result), [expected], "{name}");
+        }
     }
 
-    async fn on_summary(&mut self, _summary: runner::TestSummary) -> eyre::Result<()> {
-        self.write_history()?;
-        self.write_environment()?;
-        Ok(())
+    #[tokio::test]
+    async fn cleaning_removes_stale_files_and_keeps_foreign_ones() {
+        let results_dir = temp_dir();
+        fs::create_dir_all(results_dir.join("history")).unwrap();
+        let stale = [
+            "old-result.json",
+            "old-result.json.zst",
+            "old-container.json",
+            "old-attachment",
+            "old-attachment.gz",
+        ];
+        let foreign = ["notes.txt", "result.json.bak", "history/old-result.json"];
+        for name in stale.iter().chain(&foreign) {
+            fs::write(results_dir.join(name), b"left over").unwrap();
+        }
+
+        let mut reporter =
+            AllureReporter::with_results_dir(results_dir.to_string_lossy()).with_clean(true);
+        run_one(&mut reporter, "fresh").await;
+
+        for name in stale {
+            assert!(!results_dir.join(name).exists(), "{name}");
+        }
+        for name in foreign {
+            assert!(results_dir.join(name).exists(), "{name}");
+        }
+        let results = fs::read_dir(&results_dir)
+            .unwrap()
+            .filter_map(|entry| entry.unwrap().file_name().into_string().ok())
+            .filter(|name| name.ends_with("-result.json"))
+            .count();
+        assert_eq!(results, 1);
+        fs::remove_dir_all(&results_dir).unwrap();
     }
-}
 
-impl AllureReporter {
-    /// Writes updated history.json after all tests complete
-    fn write_history(&mut self) -> eyre::Result<()> {
-        for result in &self.current_run_results {
-            let entry = self.history.entry(result.history_id.clone()).or_default();
-
-            // Update statistics
-            entry.statistic.record(&result.status);
-
-            // Add new history item at the beginning
-            entry.items.insert(
-                0,
-                HistoryItem {
-                    uid: result.uuid.clone(),
-                    report_url: None,
-                    status: result.status.clone(),
-                    status_details: result.status_details.clone(),
-                    time: HistoryTime {
-                        start: result.start,
-                        stop: result.stop,
-                        duration: (result.stop - result.start) / 1000,
-                    },
-                },
-            );
+    #[test]
+    fn the_full_name_joins_the_module_path_and_test_with_a_hash() {
+        let (reporter, _) = reporter();
+        let test = test_case("creates_a_user", Ok(()));
+        let result = reporter
+            .map_to_allure_test_result(
+                "staging",
+                "api::users",
+                "creates_a_user",
+                &Buffer::new(1),
+                &test,
+            )
+            .unwrap();
+        assert_eq!(
+            result.full_name.as_deref(),
+            Some("staging::api::users#creates_a_user")
+        );
+        assert_eq!(result.name, "creates_a_user");
+    }
+
+    #[test]
+    fn a_markdown_description_is_rendered_into_html() {
+        let (reporter, _) = reporter();
+        let description =
+            "Creates a **user**.\n\n[x](javascript:alert(1))\n\n<script>alert(1)</script>";
+        let reporter = reporter.with_description_fn(move |_| Some(description.to_string()));
+        let result = map_test(&reporter, Vec::new(), &test_case("described", Ok(())));
+        assert_eq!(result.description.as_deref(), Some(description));
 
-            // Trim to max items
-            entry.items.truncate(MAX_HISTORY_ITEMS);
+        let html = result.description_html;
+        if cfg!(feature = "markdown") {
+            let html = html.unwrap();
+            assert!(html.contains("<strong>user</strong>"), "{html}");
+            assert!(html.contains("&lt;script&gt;"), "{html}");
+            assert!(!html.contains("javascript:"), "{html}");
+        } else {
+            assert_eq!(html, None);
         }
+    }
 
-        // Ensure history directory exists
-        let history_dir = Path::new(&self.results_dir).join("history");
-        fs::create_dir_all(&history_dir)?;
+    #[test]
+    fn the_links_hook_adds_issue_and_tms_links() {
+        let (reporter, _) = reporter();
+        let reporter = reporter.with_links_fn(|test| {
+            let url = |path: &str| format!("https://example.com/{path}").parse().unwrap();
+            vec![
+                Link::issue("API-1", url("browse/API-1")),
+                Link::tms(format!("TC-{}", test.info.name), url("cases/login")),
+            ]
+        });
+        let result = map_test(&reporter, Vec::new(), &test_case("login", Ok(())));
 
-        // Write history.json
-        let json = serde_json::to_string_pretty(&self.history)?;
-        fs::write(history_dir.join("history.json"), json)?;
+        let links = serde_json::to_value(&result.links).unwrap();
+        assert_eq!(
+            links,
+            serde_json::json!([
+                {"type": "issue", "name": "API-1", "url": "https://example.com/browse/API-1"},
+                {"type": "tms", "name": "TC-login", "url": "https://example.com/cases/login"},
+            ])
+        );
+    }
 
-        Ok(())
+    #[test]
+    fn prefixed_module_segments_become_bdd_labels() {
+        assert_eq!(
+            module_labels("api::epic_checkout::feature_cart::v2::story_add_item"),
+            [
+                Label::Suite("api::v2".to_string()),
+                Label::Epic("checkout".to_string()),
+                Label::Feature("cart".to_string()),
+                Label::Story("add_item".to_string()),
+            ]
+        );
+        assert_eq!(
+            module_labels("epic_checkout::story_pay"),
+            [
+                Label::Epic("checkout".to_string()),
+                Label::Story("pay".to_string()),
+            ]
+        );
+
+        let (reporter, _) = reporter();
+        let test = test_case("plain", Ok(()));
+        let result = map_test(&reporter.bdd_labels(true), Vec::new(), &test);
+        assert!(result.labels.contains(&Label::Suite("module".to_string())));
     }
 
-    /// Writes environment.properties file with environment variables
-    fn write_environment(&self) -> eyre::Result<()> {
-        if self.environment.is_empty() {
-            return Ok(());
+    #[tokio::test]
+    async fn results_are_indented_unless_compact_output_is_chosen() {
+        for pretty in [true, false] {
+            let (reporter, sink) = reporter();
+            let mut reporter = reporter.with_pretty(pretty);
+            run_one(&mut reporter, "formatted").await;
+
+            let files = sink.0.lock().unwrap();
+            let json_files: Vec<_> = files
+                .iter()
+                .filter(|(name, _)| name.ends_with(".json"))
+                .collect();
+            assert!(json_files.len() > 1, "{:?}", files.keys());
+            for (name, contents) in json_files {
+                let contents = std::str::from_utf8(contents).unwrap();
+                assert_eq!(contents.contains('\n'), pretty, "{name}: {contents}");
+                serde_json::from_str::<serde_json::Value>(contents).unwrap();
+            }
         }
+    }
 
-        // Ensure results directory exists
-        self.ensure_results_dir()?;
+    #[tokio::test]
+    async fn the_first_start_writes_the_static_files_once() {
+        let (reporter, sink) = reporter();
+        let mut reporter = reporter.with_default_categories();
+        start(&mut reporter, "first").await;
+        let written = |name: &str| sink.0.lock().unwrap().remove(name).is_some();
+        assert!(written("environment.properties"));
+        assert!(written("categories.json"));
 
-        // Build properties file content
-        let mut lines: Vec<String> = self
-            .environment
+        start(&mut reporter, "second").await;
+        end(&mut reporter, "first", Ok(())).await;
+        end(&mut reporter, "second", Ok(())).await;
+        assert!(!written("environment.properties"));
+        assert!(!written("categories.json"));
+
+        for result in sink.results() {
+            assert!(
+                matches!(result.stage, Some(Stage::Finished)),
+                "{}",
+                result.name
+            );
+            assert!(result.start <= result.stop, "{}", result.name);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_summary_writes_the_aggregate_files() {
+        let (reporter, sink) = reporter();
+        let mut reporter = with_fixtures(reporter).results_index(true);
+        for name in ["setup", "wrapped"] {
+            run_test(&mut reporter, name, Vec::new(), Ok(())).await;
+        }
+        let aggregates = [
+            "history/history.json",
+            "history/duration-trend.json",
+            "history/retry-trend.json",
+            "results-index.json",
+        ];
+        let has = |name: &str| sink.0.lock().unwrap().contains_key(name);
+        for name in aggregates {
+            assert!(!has(name), "{name} before the summary");
+        }
+        assert!(sink.containers().is_empty());
+
+        reporter.on_summary(summary(1)).await.unwrap();
+        for name in aggregates {
+            assert!(has(name), "{name} after the summary");
+        }
+        assert_eq!(sink.containers().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn trends_keep_the_newest_runs_first() {
+        let results_dir = temp_dir();
+        let history_dir = results_dir.join("history");
+        for _ in 0..MAX_TREND_ITEMS {
+            let mut reporter = AllureReporter::with_results_dir(results_dir.to_string_lossy());
+            run_one(&mut reporter, "steady").await;
+        }
+        let mut reporter = AllureReporter::with_results_dir(results_dir.to_string_lossy());
+        run_test(&mut reporter, "steady", Vec::new(), Ok(())).await;
+        run_test(&mut reporter, "added", Vec::new(), Ok(())).await;
+        reporter.on_summary(summary(2)).await.unwrap();
+
+        let durations = read_json(&history_dir.join("duration-trend.json"));
+        let durations = durations.as_array().unwrap();
+        assert_eq!(durations.len(), MAX_TREND_ITEMS);
+        assert!(durations
             .iter()
-            .map(|(key, value)| {
-                // Escape special characters for Java properties format
-                let escaped_key = key
-                    .replace('\\', "\\\\")
-                    .replace('=', "\\=")
-                    .replace(':', "\\:");
-                let escaped_value = value
-                    .replace('\\', "\\\\")
-                    .replace('\n', "\\n")
-                    .replace('\r', "\\r");
-                format!("{} = {}", escaped_key, escaped_value)
+            .all(|item| item["data"]["duration"].is_i64()));
+
+        let retries = read_json(&history_dir.join("retry-trend.json"));
+        let retries = retries.as_array().unwrap();
+        assert_eq!(retries.len(), MAX_TREND_ITEMS);
+        assert_eq!(
+            retries[0]["data"],
+            serde_json::json!({"run": 2, "retry": 0})
+        );
+        assert_eq!(
+            retries[1]["data"],
+            serde_json::json!({"run": 1, "retry": 0})
+        );
+        fs::remove_dir_all(&results_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn result_step_and_history_times_are_all_milliseconds() {
+        let (mut reporter, sink) = reporter();
+        let started_at = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let mut log = http_log("https://example.com/", None, "");
+        log.started_at = started_at + Duration::from_millis(100);
+        log.ended_at = started_at + Duration::from_millis(350);
+        start(&mut reporter, "timed").await;
+        deliver(&mut reporter, "timed", Event::Http(Box::new(log))).await;
+        let test = Test {
+            started_at,
+            ended_at: started_at + Duration::from_millis(1_500),
+            ..test_case("timed", Ok(()))
+        };
+        reporter
+            .on_end("project".into(), "module".into(), "timed".into(), test)
+            .await
+            .unwrap();
+        reporter.on_summary(summary(1)).await.unwrap();
+
+        let [result] = sink.results().try_into().unwrap();
+        assert_eq!(result.start, Some(1_700_000_000_123));
+        assert_eq!(result.stop, Some(1_700_000_001_623));
+        let step = &result.steps[0];
+        assert_eq!(
+            (step.start, step.stop),
+            (Some(1_700_000_000_223), Some(1_700_000_000_473))
+        );
+
+        let history: History = serde_json::from_slice(&sink.file("history/history.json")).unwrap();
+        let time = &history[&result.history_id].items[0].time;
+        assert_eq!(
+            (time.start, time.stop),
+            (1_700_000_000_123, 1_700_000_001_623)
+        );
+        assert_eq!(time.duration, 1_500);
+    }
+
+    #[test]
+    fn global_labels_follow_the_suites_without_repeating_a_tag() {
+        let (reporter, _) = reporter();
+        let reporter = reporter
+            .host_thread_labels(false)
+            .with_global_labels(vec![
+                Label::custom("team", "payments"),
+                Label::Tag("smoke".to_string()),
+            ])
+            .with_tags_fn(|_| vec!["smoke".to_string()]);
+        let result = map_test(&reporter, Vec::new(), &test_case("labeled", Ok(())));
+
+        assert_eq!(
+            result.labels,
+            [
+                Label::ParentSuite("project".to_string()),
+                Label::Suite("module".to_string()),
+                Label::custom("team", "payments"),
+                Label::Tag("smoke".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn host_and_thread_labels_name_the_machine_and_worker() {
+        let (reporter, _) = reporter();
+        let test = Test {
+            worker_id: 3,
+            ..test_case("threaded", Ok(()))
+        };
+        let result = map_test(&reporter, Vec::new(), &test);
+        let host = result.labels.iter().find_map(|label| match label {
+            Label::Host(host) => Some(host.as_str()),
+            _ => None,
+        });
+        assert!(host.is_some_and(|host| !host.is_empty()), "{host:?}");
+        assert!(result.labels.contains(&Label::Thread("3".to_string())));
+
+        let result = map_test(&reporter.host_thread_labels(false), Vec::new(), &test);
+        assert!(!result
+            .labels
+            .iter()
+            .any(|label| matches!(label, Label::Host(_) | Label::Thread(_))));
+    }
+
+    #[tokio::test]
+    async fn the_results_index_maps_each_uuid_to_its_test() {
+        let (reporter, sink) = reporter();
+        let mut reporter = reporter.results_index(true);
+        for name in ["first", "second"] {
+            run_test(&mut reporter, name, Vec::new(), Ok(())).await;
+        }
+        reporter.on_summary(summary(2)).await.unwrap();
+
+        let index: serde_json::Value =
+            serde_json::from_slice(&sink.file("results-index.json")).unwrap();
+        let expected: serde_json::Map<_, _> = sink
+            .results()
+            .into_iter()
+            .map(|result| {
+                let full_name = format!("project::module#{}", result.name);
+                (result.uuid.to_string(), full_name.into())
             })
             .collect();
+        assert_eq!(expected.len(), 2);
+        assert_eq!(index, serde_json::Value::Object(expected));
+        for uuid in index.as_object().unwrap().keys() {
+            let file_name = format!("{uuid}-result.json");
+            assert!(
+                sink.0.lock().unwrap().contains_key(&file_name),
+                "{file_name}"
+            );
+        }
+    }
 
-        // Sort for deterministic output
-        lines.sort();
+    #[tokio::test]
+    async fn a_test_that_never_started_still_gets_a_result() {
+        let (reporter, sink) = reporter();
+        let mut reporter = reporter.start_order_parameter(true);
+        run_test(&mut reporter, "started", Vec::new(), Ok(())).await;
+        end(&mut reporter, "unannounced", failed()).await;
+        reporter.on_summary(summary(2)).await.unwrap();
 
-        // Write environment.properties file
-        let file_path = Path::new(&self.results_dir).join("environment.properties");
-        fs::write(file_path, lines.join("\n"))?;
+        let results = sink.results();
+        let result = results.iter().find(|r| r.name == "unannounced").unwrap();
+        assert_eq!(result.status, Status::Failed);
+        assert!(result.steps.is_empty());
+        let order = result.parameters.iter().find(|p| p.name == "start_order");
+        assert_eq!(order.map(|p| p.value.as_str()), Some("2"));
+        assert!(reporter.buffer.is_empty());
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn results_and_containers_are_named_by_the_uuid_source() {
+        let (reporter, sink) = reporter();
+        let mut reporter = with_fixtures(reporter);
+        for name in ["setup", "first", "second"] {
+            run_test(&mut reporter, name, Vec::new(), Ok(())).await;
+        }
+        reporter.on_summary(summary(2)).await.unwrap();
+
+        // The container is created with the module's first fixture, before any result
+        let files = sink.0.lock().unwrap();
+        let named: Vec<_> = files
+            .keys()
+            .filter(|name| name.ends_with("-result.json") || name.ends_with("-container.json"))
+            .map(String::as_str)
+            .collect();
+        assert_eq!(
+            named,
+            [
+                "00000000-0000-0000-0000-000000000001-container.json",
+                "00000000-0000-0000-0000-000000000002-result.json",
+                "00000000-0000-0000-0000-000000000003-result.json",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fixture_output_is_attached_to_the_containers_fixture() {
+        let (reporter, sink) = reporter();
+        let mut reporter = reporter.attach_output(true).with_fixture_fn(|test| {
+            (test.info.name == "seeds_users").then_some(FixtureKind::Before)
+        });
+        let project = Arc::new(tanu_core::ProjectConfig {
+            name: "project".to_string(),
+            ..Default::default()
+        });
+        tanu_core::config::PROJECT
+            .scope(
+                project,
+                output::capture("module", "seeds_users", async {
+                    crate::allure_eprintln!("seeded 3 users");
+                }),
+            )
+            .await;
+        for name in ["seeds_users", "lists_users"] {
+            run_test(&mut reporter, name, Vec::new(), Ok(())).await;
+        }
+        reporter.on_summary(summary(1)).await.unwrap();
+
+        let [container] = sink.containers().try_into().unwrap();
+        let [fixture] = &container.befores[..] else {
+            panic!(
+                "expected one before fixture, got {}",
+                container.befores.len()
+            );
+        };
+        assert_eq!(fixture.name, "seeds_users");
+        let [attachment] = &fixture.attachments[..] else {
+            panic!("expected one attachment, got {}", fixture.attachments.len());
+        };
+        assert_eq!(attachment.name, "stderr");
+        assert_eq!(sink.file(&attachment.source), b"seeded 3 users\n");
+        let [result] = sink.results().try_into().unwrap();
+        assert!(result.attachments.is_empty());
     }
 }
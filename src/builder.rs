@@ -0,0 +1,210 @@
+//! A builder that configures an [`AllureReporter`] in one chain and validates it at the end.
+//!
+//! Every option of the reporter has a consuming `with_*` setter here, including the ones
+//! the reporter itself only offers as `&mut self` methods. Options that can be invalid,
+//! such as regular expressions and JSON Schemas, are only checked by
+//! [`build`](AllureReporterBuilder::build), which reports the first invalid one, as well as
+//! options that cannot be combined.
+use std::collections::HashMap;
+use tanu_core::{
+    eyre::{self, WrapErr},
+    runner::{self, Test},
+};
+
+use crate::adapter::{AllureReporter, Compression, FixtureKind, RunSummary};
+use crate::models::{Category, Executor, HashAlgo, HistoryIdConfig, Label, Link, Status};
+use crate::sink::ResultSink;
+
+/// Generates setters that forward to the consuming [`AllureReporter`] method of an option
+macro_rules! forward {
+    ($($setter:ident => $method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            #[doc = concat!("See [`AllureReporter::", stringify!($method), "`].")]
+            pub fn $setter(mut self, $($arg: $ty),*) -> Self {
+                self.reporter = self.reporter.$method($($arg),*);
+                self
+            }
+        )*
+    };
+}
+
+/// Configures an [`AllureReporter`], e.g.
+///
+/// ```
+/// let reporter = tanu_allure::AllureReporterBuilder::new("target/allure-results")
+///     .with_clean(true)
+///     .with_masked_header("x-session-id")
+///     .with_masked_header_pattern(".*-token")
+///     .with_results_index(true)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct AllureReporterBuilder {
+    reporter: AllureReporter,
+    masked_header_patterns: Vec<String>,
+    redacted_body_key_patterns: Vec<String>,
+    #[cfg(feature = "json-schema")]
+    response_schemas: Vec<(String, serde_json::Value)>,
+}
+
+impl Default for AllureReporterBuilder {
+    /// Starts from [`AllureReporter::new`], writing into `allure-results`.
+    fn default() -> Self {
+        Self::new("allure-results")
+    }
+}
+
+impl AllureReporterBuilder {
+    /// Starts from [`AllureReporter::with_results_dir`].
+    pub fn new(results_dir: impl Into<String>) -> Self {
+        AllureReporterBuilder {
+            reporter: AllureReporter::with_results_dir(results_dir),
+            masked_header_patterns: Vec::new(),
+            redacted_body_key_patterns: Vec::new(),
+            #[cfg(feature = "json-schema")]
+            response_schemas: Vec::new(),
+        }
+    }
+
+    /// Checks the options and returns the configured reporter.
+    ///
+    /// Fails on the first invalid pattern or schema, and when options are combined that the
+    /// reporter would otherwise only reject once the run finishes, e.g.
+    /// [`with_clean`](Self::with_clean) with
+    /// [`with_shared_results_dir`](Self::with_shared_results_dir).
+    pub fn build(self) -> eyre::Result<AllureReporter> {
+        let mut reporter = self.reporter;
+        for pattern in &self.masked_header_patterns {
+            reporter
+                .add_masked_header_pattern(pattern)
+                .wrap_err_with(|| format!("invalid masked header pattern \"{pattern}\""))?;
+        }
+        for pattern in &self.redacted_body_key_patterns {
+            reporter
+                .add_redacted_body_key_pattern(pattern)
+                .wrap_err_with(|| format!("invalid redacted body key pattern \"{pattern}\""))?;
+        }
+        #[cfg(feature = "json-schema")]
+        for (path_pattern, schema) in &self.response_schemas {
+            reporter.add_response_schema(path_pattern, schema)?;
+        }
+        reporter.validate()?;
+        Ok(reporter)
+    }
+
+    /// See [`AllureReporter::add_environment`].
+    pub fn with_environment_value(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.reporter.add_environment(key, value);
+        self
+    }
+
+    /// See [`AllureReporter::load_from_env`].
+    pub fn with_environment_prefix(mut self, prefix: &str) -> Self {
+        self.reporter.load_from_env(prefix);
+        self
+    }
+
+    /// See [`AllureReporter::add_masked_header`].
+    pub fn with_masked_header(mut self, name: &str) -> Self {
+        self.reporter.add_masked_header(name);
+        self
+    }
+
+    /// See [`AllureReporter::add_masked_query_param`].
+    pub fn with_masked_query_param(mut self, name: &str) -> Self {
+        self.reporter.add_masked_query_param(name);
+        self
+    }
+
+    /// See [`AllureReporter::add_redacted_body_key`].
+    pub fn with_redacted_body_key(mut self, name: &str) -> Self {
+        self.reporter.add_redacted_body_key(name);
+        self
+    }
+
+    /// See [`AllureReporter::add_masked_header_pattern`]; the pattern is checked by
+    /// [`build`](Self::build).
+    pub fn with_masked_header_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.masked_header_patterns.push(pattern.into());
+        self
+    }
+
+    /// See [`AllureReporter::add_redacted_body_key_pattern`]; the pattern is checked by
+    /// [`build`](Self::build).
+    pub fn with_redacted_body_key_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.redacted_body_key_patterns.push(pattern.into());
+        self
+    }
+
+    /// See [`AllureReporter::add_response_schema`]; the pattern and schema are checked by
+    /// [`build`](Self::build).
+    #[cfg(feature = "json-schema")]
+    pub fn with_response_schema(
+        mut self,
+        path_pattern: impl Into<String>,
+        schema: serde_json::Value,
+    ) -> Self {
+        self.response_schemas.push((path_pattern.into(), schema));
+        self
+    }
+
+    forward! {
+        with_environment => with_environment(env: HashMap<String, String>);
+        with_masked_headers => with_masked_headers(names: impl IntoIterator<Item = impl AsRef<str>>);
+        with_max_body_bytes => max_body_bytes(limit: u64);
+        with_attachment_compression => with_attachment_compression(min_bytes: usize);
+        with_inline_attachments => with_inline_attachments(max_bytes: usize);
+        with_max_header_bytes => max_header_bytes(limit: usize);
+        with_test_case_id_algo => test_case_id_algo(algo: HashAlgo);
+        with_fixture_fn => with_fixture_fn(fixture_fn: impl Fn(&Test) -> Option<FixtureKind> + Send + 'static);
+        with_finish_fn => on_finish(callback: impl Fn(&runner::TestSummary, &RunSummary) + Send + 'static);
+        with_informational_status => informational_status(status: Status);
+        with_output_attachments => attach_output(enabled: bool);
+        with_incremental_history => incremental_history(enabled: bool);
+        with_shared_results_dir => shared_results_dir(enabled: bool);
+        with_error_mapper => with_error_mapper(mapper: impl Fn(&runner::Error) -> Status + Send + 'static);
+        with_environment_label => environment_label(enabled: bool);
+        with_environment_in_history_id => environment_in_history_id(enabled: bool);
+        with_timeline_attachment => attach_timeline(enabled: bool);
+        with_skipped_body_types => skip_body_types(types: Vec<String>);
+        with_media_type_overrides => with_media_type_overrides(overrides: HashMap<String, String>);
+        with_compression => compress(compression: Compression);
+        with_coalesced_http_calls => coalesce_http_calls(enabled: bool);
+        with_tags_fn => with_tags_fn(tags_fn: impl Fn(&Test) -> Vec<String> + Send + 'static);
+        with_start_order_parameter => start_order_parameter(enabled: bool);
+        with_stdout_json => stdout_json(enabled: bool);
+        with_version_label => version_label(enabled: bool);
+        with_history_id_components => history_id_components(config: HistoryIdConfig);
+        with_har_attachment => attach_har(enabled: bool);
+        with_project_parameter => project_parameter(enabled: bool);
+        with_host_thread_labels => host_thread_labels(enabled: bool);
+        with_header_parameters => header_parameters(enabled: bool);
+        with_http_parameters => http_parameters(enabled: bool);
+        with_service_labels => service_labels(enabled: bool);
+        with_minimal_output => minimal();
+        with_categories => with_categories(categories: Vec<Category>);
+        with_default_categories => with_default_categories();
+        with_history_dir => with_history_dir(path: impl AsRef<std::path::Path>);
+        with_severity_fn => with_severity_fn(severity_fn: impl Fn(&Test) -> Option<String> + Send + 'static);
+        with_default_severity => with_default_severity(severity: impl Into<String>);
+        with_clean => with_clean(enabled: bool);
+        with_executor => with_executor(executor: Executor);
+        with_step_grouping => with_step_grouping(enabled: bool);
+        with_description_fn => with_description_fn(description_fn: impl Fn(&Test) -> Option<String> + Send + 'static);
+        with_links_fn => with_links_fn(links_fn: impl Fn(&Test) -> Vec<Link> + Send + 'static);
+        with_bdd_labels => bdd_labels(enabled: bool);
+        with_pretty => with_pretty(pretty: bool);
+        with_sink => with_sink(sink: impl ResultSink + 'static);
+        with_global_labels => with_global_labels(labels: Vec<Label>);
+        with_results_index => results_index(enabled: bool);
+        with_issue_url_template => with_issue_url_template(template: impl Into<String>);
+        with_flaky_history_window => flaky_history_window(runs: usize);
+        with_curl_attachments => with_curl_attachments(enabled: bool);
+        with_uuid_fn => with_uuid_fn(uuid_fn: impl Fn() -> uuid::Uuid + Send + 'static);
+        with_print_summary => print_summary(enabled: bool);
+    }
+}
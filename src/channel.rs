@@ -0,0 +1,345 @@
+//! A reporter that hands events to a dedicated writer task.
+//!
+//! tanu drives a reporter from a single task, so writing results and attachments to disk
+//! inside the callbacks holds up every event behind it. [`ChannelReporter`] only forwards
+//! each event over a bounded channel; a spawned task feeds them to the wrapped reporter,
+//! usually an [`AllureReporter`](crate::AllureReporter), which does the I/O. When the
+//! writer falls behind by `capacity` events, forwarding waits until it catches up.
+use tanu_core::{
+    eyre,
+    runner::{self, Test},
+    Reporter,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+enum Message {
+    Start(String, String, String),
+    Check(String, String, String, Box<runner::Check>),
+    Call(String, String, String, runner::CallLog),
+    Retry(String, String, String, Test),
+    End(String, String, String, Test),
+    Summary(runner::TestSummary),
+}
+
+/// Forwards reporter events to another reporter running on its own task.
+///
+/// The writer task is spawned on the current tokio runtime. Like tanu itself, it logs an
+/// error returned by the wrapped reporter and keeps delivering the following events, so a
+/// failed test result does not cost the rest of the report. `on_summary` waits for all events
+/// to be written and returns the errors of the whole run, including its own.
+pub struct ChannelReporter {
+    sender: Option<mpsc::Sender<Message>>,
+    writer: Option<JoinHandle<eyre::Result<()>>>,
+}
+
+impl ChannelReporter {
+    /// Spawns the writer task for `reporter`, buffering at most `capacity` events.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a tokio runtime or if `capacity` is 0.
+    pub fn new<R>(mut reporter: R, capacity: usize) -> Self
+    where
+        R: Reporter + Send + 'static,
+    {
+        let (sender, mut receiver) = mpsc::channel(capacity);
+        let writer = tokio::spawn(async move {
+            let mut errors = Vec::new();
+            while let Some(message) = receiver.recv().await {
+                let (event, result) = match message {
+                    Message::Start(project, module, test_name) => (
+                        format!("the start of {project}::{module}::{test_name}"),
+                        reporter.on_start(project, module, test_name).await,
+                    ),
+                    Message::Check(project, module, test_name, check) => (
+                        format!("a check of {project}::{module}::{test_name}"),
+                        reporter.on_check(project, module, test_name, check).await,
+                    ),
+                    Message::Call(project, module, test_name, log) => (
+                        format!("a call of {project}::{module}::{test_name}"),
+                        reporter.on_call(project, module, test_name, log).await,
+                    ),
+                    Message::Retry(project, module, test_name, test) => (
+                        format!("the retry of {project}::{module}::{test_name}"),
+                        reporter.on_retry(project, module, test_name, test).await,
+                    ),
+                    Message::End(project, module, test_name, test) => (
+                        format!("the end of {project}::{module}::{test_name}"),
+                        reporter.on_end(project, module, test_name, test).await,
+                    ),
+                    Message::Summary(summary) => (
+                        "the summary".to_string(),
+                        reporter.on_summary(summary).await,
+                    ),
+                };
+                if let Err(e) = result {
+                    eprintln!("tanu-allure: reporter failed to handle {event}: {e:#}");
+                    errors.push(format!("{event}: {e:#}"));
+                }
+            }
+            match errors.as_slice() {
+                [] => Ok(()),
+                [error] => Err(eyre::eyre!("reporter failed to handle {error}")),
+                errors => Err(eyre::eyre!(
+                    "reporter failed to handle {} events: {}",
+                    errors.len(),
+                    errors.join("; ")
+                )),
+            }
+        });
+
+        ChannelReporter {
+            sender: Some(sender),
+            writer: Some(writer),
+        }
+    }
+
+    async fn send(&mut self, message: Message) -> eyre::Result<()> {
+        let delivered = match &self.sender {
+            Some(sender) => sender.send(message).await.is_ok(),
+            None => false,
+        };
+        if delivered {
+            Ok(())
+        } else {
+            // The writer task only stops early if it panicked; hand that to the caller
+            self.sender = None;
+            self.join_writer().await
+        }
+    }
+
+    async fn join_writer(&mut self) -> eyre::Result<()> {
+        match self.writer.take() {
+            Some(writer) => writer
+                .await
+                .map_err(|e| eyre::eyre!("reporter writer task failed: {e}"))?,
+            None => Err(eyre::eyre!("reporter writer task has already stopped")),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Reporter for ChannelReporter {
+    async fn on_start(
+        &mut self,
+        project: String,
+        module: String,
+        test_name: String,
+    ) -> eyre::Result<()> {
+        self.send(Message::Start(project, module, test_name)).await
+    }
+
+    async fn on_check(
+        &mut self,
+        project: String,
+        module: String,
+        test_name: String,
+        check: Box<runner::Check>,
+    ) -> eyre::Result<()> {
+        self.send(Message::Check(project, module, test_name, check))
+            .await
+    }
+
+    async fn on_call(
+        &mut self,
+        project: String,
+        module: String,
+        test_name: String,
+        log: runner::CallLog,
+    ) -> eyre::Result<()> {
+        self.send(Message::Call(project, module, test_name, log))
+            .await
+    }
+
+    async fn on_retry(
+        &mut self,
+        project: String,
+        module: String,
+        test_name: String,
+        test: Test,
+    ) -> eyre::Result<()> {
+        self.send(Message::Retry(project, module, test_name, test))
+            .await
+    }
+
+    async fn on_end(
+        &mut self,
+        project: String,
+        module: String,
+        test_name: String,
+        test: Test,
+    ) -> eyre::Result<()> {
+        self.send(Message::End(project, module, test_name, test))
+            .await
+    }
+
+    async fn on_summary(&mut self, summary: runner::TestSummary) -> eyre::Result<()> {
+        self.send(Message::Summary(summary)).await?;
+        // Closing the channel lets the writer finish once it has drained every event
+        self.sender = None;
+        self.join_writer().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AllureReporter;
+    use std::{
+        fs,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, SystemTime},
+    };
+
+    fn test_case(name: &str) -> Test {
+        let now = SystemTime::now();
+        Test {
+            info: Arc::new(runner::TestInfo {
+                module: "module".to_string(),
+                name: name.to_string(),
+                ..Default::default()
+            }),
+            worker_id: 0,
+            started_at: now,
+            ended_at: now,
+            request_time: Duration::ZERO,
+            result: Ok(()),
+        }
+    }
+
+    fn summary(total_tests: usize) -> runner::TestSummary {
+        runner::TestSummary {
+            total_tests,
+            passed_tests: total_tests,
+            failed_tests: 0,
+            skipped_tests: 0,
+            total_time: Duration::ZERO,
+            test_prep_time: Duration::ZERO,
+        }
+    }
+
+    fn results_dir() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("tanu-allure-channel-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn interleaved_events_of_many_tests_are_all_written() {
+        const TESTS: usize = 100;
+        let dir = results_dir();
+        let reporter =
+            ChannelReporter::new(AllureReporter::with_results_dir(dir.to_string_lossy()), 4);
+        let reporter = Arc::new(tokio::sync::Mutex::new(reporter));
+
+        // Every test sends its events from its own task, as tanu's workers do
+        let tests: Vec<_> = (0..TESTS)
+            .map(|i| {
+                let reporter = reporter.clone();
+                tokio::spawn(async move {
+                    let name = format!("test_{i}");
+                    let (project, module) = ("project".to_string(), "module".to_string());
+                    let check = Box::new(runner::Check::success(format!("check of {name}")));
+                    reporter
+                        .lock()
+                        .await
+                        .on_start(project.clone(), module.clone(), name.clone())
+                        .await
+                        .unwrap();
+                    tokio::task::yield_now().await;
+                    reporter
+                        .lock()
+                        .await
+                        .on_check(project.clone(), module.clone(), name.clone(), check)
+                        .await
+                        .unwrap();
+                    tokio::task::yield_now().await;
+                    let test = test_case(&name);
+                    reporter
+                        .lock()
+                        .await
+                        .on_end(project, module, name, test)
+                        .await
+                        .unwrap();
+                })
+            })
+            .collect();
+        for test in tests {
+            test.await.unwrap();
+        }
+        reporter
+            .lock()
+            .await
+            .on_summary(summary(TESTS))
+            .await
+            .unwrap();
+
+        let results: Vec<serde_json::Value> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().ends_with("-result.json"))
+            .map(|path| serde_json::from_slice(&fs::read(path).unwrap()).unwrap())
+            .collect();
+        assert_eq!(results.len(), TESTS);
+        for result in &results {
+            let steps = result["steps"].as_array().unwrap();
+            assert_eq!(steps.len(), 1, "{}", result["name"]);
+            let expected = format!("check of {}", result["name"].as_str().unwrap());
+            assert_eq!(steps[0]["name"], expected.as_str());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Fails to handle the end of `failing` and counts the events it sees
+    #[derive(Default)]
+    struct Flaky {
+        ended: Arc<AtomicUsize>,
+        summarized: Arc<AtomicBool>,
+    }
+
+    #[async_trait::async_trait]
+    impl Reporter for Flaky {
+        async fn on_end(
+            &mut self,
+            _project: String,
+            _module: String,
+            test_name: String,
+            _test: Test,
+        ) -> eyre::Result<()> {
+            eyre::ensure!(test_name != "failing", "disk full");
+            self.ended.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn on_summary(&mut self, _summary: runner::TestSummary) -> eyre::Result<()> {
+            self.summarized.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn an_error_does_not_drop_the_following_events() {
+        let flaky = Flaky::default();
+        let (ended, summarized) = (flaky.ended.clone(), flaky.summarized.clone());
+        let mut reporter = ChannelReporter::new(flaky, 1);
+
+        for name in ["before", "failing", "after"] {
+            reporter
+                .on_end(
+                    "project".into(),
+                    "module".into(),
+                    name.into(),
+                    test_case(name),
+                )
+                .await
+                .unwrap();
+        }
+        let error = reporter.on_summary(summary(3)).await.unwrap_err();
+
+        assert_eq!(ended.load(Ordering::SeqCst), 2);
+        assert!(summarized.load(Ordering::SeqCst));
+        let error = error.to_string();
+        assert!(error.contains("project::module::failing") && error.contains("disk full"));
+    }
+}
@@ -1,4 +1,13 @@
 pub mod adapter;
+pub mod builder;
+pub mod channel;
+pub mod merge;
 pub mod models;
+pub mod output;
+pub mod sink;
 
 pub use adapter::AllureReporter;
+pub use builder::AllureReporterBuilder;
+pub use channel::ChannelReporter;
+pub use merge::merge_results_dirs;
+pub use sink::{FilesystemSink, ResultSink};
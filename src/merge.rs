@@ -0,0 +1,350 @@
+//! Combining the results directories of sharded runs into one.
+//!
+//! CI jobs that split a suite across machines each produce their own `allure-results`.
+//! [`merge_results_dirs`] copies them into a single directory that Allure can turn into one
+//! report, merging the history of every shard instead of keeping only the last one copied.
+use std::{fs, io, path::Path};
+use tanu_core::eyre::{self, WrapErr};
+
+use crate::models::{History, MAX_HISTORY_ITEMS};
+use crate::sink::{self, TEMP_FILE_SUFFIX};
+
+/// Run-wide files every shard writes with the same content; the first copy is kept
+const SHARED_FILES: &[&str] = &[
+    "environment.properties",
+    "categories.json",
+    "executor.json",
+    "COMPRESSED.txt",
+];
+
+/// Trend files of the history directory, taken from the first source that has them
+const TREND_FILES: &[&str] = &["duration-trend.json", "retry-trend.json"];
+
+/// Merges the results directories in `sources` into `dest`, creating it if needed.
+///
+/// Results, containers and attachments are copied as they are. Their names are uuids or
+/// content hashes, so the only collisions expected are identical attachments; a file that
+/// exists with different content fails the merge. The `history/history.json` of every
+/// source, and of `dest` if it has one, are combined: statistics are summed and items are
+/// concatenated, newest first, keeping at most [`MAX_HISTORY_ITEMS`]. `results-index.json`
+/// files are combined as well, while environment, categories, executor and trend files are
+/// taken from the first source that has them.
+///
+/// Statistics count every run each directory has seen, so the shards of a run should not
+/// all carry the same earlier history; include it once, e.g. as the existing history of
+/// `dest`. `dest` itself must not be one of `sources`.
+pub fn merge_results_dirs(sources: &[impl AsRef<Path>], dest: &Path) -> eyre::Result<()> {
+    fs::create_dir_all(dest).wrap_err_with(|| format!("failed to create {}", dest.display()))?;
+    let mut history = read_history(dest)?.unwrap_or_default();
+    let mut index = read_index(&dest.join("results-index.json"))?;
+
+    for source in sources {
+        let source = source.as_ref();
+        let entries = fs::read_dir(source)
+            .wrap_err_with(|| format!("failed to read {}", source.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if name.ends_with(TEMP_FILE_SUFFIX) || name.starts_with(".probe-") {
+                continue;
+            }
+            if name == "results-index.json" {
+                index.extend(read_index(&entry.path())?);
+                continue;
+            }
+            copy_file(
+                &entry.path(),
+                &dest.join(&name),
+                SHARED_FILES.contains(&name.as_str()),
+            )?;
+        }
+
+        if let Some(source_history) = read_history(source)? {
+            merge_history(&mut history, source_history);
+        }
+        for trend in TREND_FILES {
+            let path = source.join("history").join(trend);
+            let target = dest.join("history").join(trend);
+            if path.is_file() && !target.exists() {
+                fs::create_dir_all(dest.join("history"))?;
+                fs::copy(&path, &target)
+                    .wrap_err_with(|| format!("failed to copy {}", path.display()))?;
+            }
+        }
+    }
+
+    if !history.is_empty() {
+        fs::create_dir_all(dest.join("history"))?;
+        let json = serde_json::to_string_pretty(&history)?;
+        sink::write_atomically(&dest.join("history").join("history.json"), json.as_bytes())?;
+    }
+    if !index.is_empty() {
+        let json = serde_json::to_string_pretty(&index)?;
+        sink::write_atomically(&dest.join("results-index.json"), json.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Adds the entries of `from` to `into`, summing the statistics of tests found in both.
+///
+/// Shards of one run usually continue the same earlier history, so a run whose item is
+/// already in `into` is only counted once, and an entry without new items not at all.
+fn merge_history(into: &mut History, from: History) {
+    for (history_id, entry) in from {
+        let target = into.entry(history_id).or_default();
+        let mut statistic = entry.statistic;
+        let mut added = false;
+        for item in entry.items {
+            if target.items.iter().any(|existing| existing.uid == item.uid) {
+                statistic.forget(&item.status);
+            } else {
+                target.items.push(item);
+                added = true;
+            }
+        }
+        // An entry adding no run repeats one already merged, including the runs counted in
+        // its statistic beyond the kept items
+        if added || target.items.is_empty() {
+            target.statistic.merge(&statistic);
+        }
+        target
+            .items
+            .sort_by_key(|item| std::cmp::Reverse(item.time.start));
+        target.items.truncate(MAX_HISTORY_ITEMS);
+    }
+}
+
+/// Copies `from` to `to`, accepting an identical existing file and, for run-wide files,
+/// keeping whichever copy came first
+fn copy_file(from: &Path, to: &Path, shared: bool) -> eyre::Result<()> {
+    if to.exists() {
+        if shared || fs::read(from)? == fs::read(to)? {
+            return Ok(());
+        }
+        eyre::bail!(
+            "{} already exists with different content than {}",
+            to.display(),
+            from.display()
+        );
+    }
+    fs::copy(from, to).wrap_err_with(|| format!("failed to copy {}", from.display()))?;
+    Ok(())
+}
+
+fn read_history(results_dir: &Path) -> eyre::Result<Option<History>> {
+    let path = results_dir.join("history").join("history.json");
+    read_json(&path)
+}
+
+fn read_index(path: &Path) -> eyre::Result<serde_json::Map<String, serde_json::Value>> {
+    Ok(read_json(path)?.unwrap_or_default())
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> eyre::Result<Option<T>> {
+    match fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .map(Some)
+            .wrap_err_with(|| format!("failed to parse {}", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).wrap_err_with(|| format!("failed to read {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{HistoryEntry, HistoryItem, HistoryTime, Status};
+    use std::path::PathBuf;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("tanu-allure-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn item(uid: &str, status: Status, start: i64) -> HistoryItem {
+        HistoryItem {
+            uid: uid.to_string(),
+            report_url: None,
+            status,
+            status_details: None,
+            time: HistoryTime {
+                start,
+                stop: start + 10,
+                duration: 10,
+            },
+        }
+    }
+
+    fn entry(items: Vec<HistoryItem>) -> HistoryEntry {
+        let mut entry = HistoryEntry::default();
+        for item in &items {
+            entry.statistic.record(&item.status);
+        }
+        entry.items = items;
+        entry
+    }
+
+    /// Creates a results directory holding `files` and `history`
+    fn seed(files: &[(&str, &str)], history: History) -> PathBuf {
+        let dir = temp_dir();
+        fs::create_dir_all(dir.join("history")).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        fs::write(
+            dir.join("history").join("history.json"),
+            serde_json::to_vec(&history).unwrap(),
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn merging_two_shards_combines_their_files_and_history() {
+        let first = seed(
+            &[
+                ("a-result.json", "{\"name\":\"a\"}"),
+                ("same-attachment.txt", "same"),
+                ("environment.properties", "shard=1"),
+                ("results-index.json", "{\"a\":\"project::module#a\"}"),
+            ],
+            History::from([
+                (
+                    "shared".to_string(),
+                    entry(vec![item("a1", Status::Passed, 100)]),
+                ),
+                (
+                    "first".to_string(),
+                    entry(vec![item("a2", Status::Passed, 100)]),
+                ),
+            ]),
+        );
+        let second = seed(
+            &[
+                ("b-result.json", "{\"name\":\"b\"}"),
+                ("same-attachment.txt", "same"),
+                ("environment.properties", "shard=2"),
+                ("results-index.json", "{\"b\":\"project::module#b\"}"),
+            ],
+            History::from([(
+                "shared".to_string(),
+                entry(vec![item("b1", Status::Failed, 200)]),
+            )]),
+        );
+        let dest = temp_dir();
+
+        merge_results_dirs(&[&first, &second], &dest).unwrap();
+
+        for name in ["a-result.json", "b-result.json", "same-attachment.txt"] {
+            assert!(dest.join(name).is_file(), "{name}");
+        }
+        assert_eq!(
+            fs::read_to_string(dest.join("environment.properties")).unwrap(),
+            "shard=1"
+        );
+        let index = read_index(&dest.join("results-index.json")).unwrap();
+        assert_eq!(index.len(), 2);
+
+        let history = read_history(&dest).unwrap().unwrap();
+        assert_eq!(history.len(), 2);
+        let shared = &history["shared"];
+        assert_eq!(shared.statistic.total, 2);
+        assert_eq!(shared.statistic.passed, 1);
+        assert_eq!(shared.statistic.failed, 1);
+        let uids: Vec<_> = shared.items.iter().map(|item| item.uid.as_str()).collect();
+        assert_eq!(uids, ["b1", "a1"]);
+        assert_eq!(history["first"].statistic.total, 1);
+
+        for dir in [first, second, dest] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn merged_history_keeps_the_newest_items() {
+        let shard = |offset: i64| {
+            let items = (0..15)
+                .map(|i| item(&format!("{offset}-{i}"), Status::Passed, offset + i))
+                .collect();
+            seed(&[], History::from([("test".to_string(), entry(items))]))
+        };
+        let (first, second) = (shard(0), shard(100));
+        let dest = temp_dir();
+
+        merge_results_dirs(&[&first, &second], &dest).unwrap();
+
+        let history = read_history(&dest).unwrap().unwrap();
+        let entry = &history["test"];
+        assert_eq!(entry.statistic.total, 30);
+        assert_eq!(entry.items.len(), MAX_HISTORY_ITEMS);
+        assert_eq!(entry.items[0].time.start, 114);
+        assert_eq!(entry.items[MAX_HISTORY_ITEMS - 1].time.start, 10);
+
+        for dir in [first, second, dest] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn a_file_with_different_content_fails_the_merge() {
+        let first = seed(&[("a-result.json", "{\"name\":\"a\"}")], History::new());
+        let second = seed(&[("a-result.json", "{\"name\":\"b\"}")], History::new());
+        let dest = temp_dir();
+
+        let error = merge_results_dirs(&[&first, &second], &dest).unwrap_err();
+        assert!(error.to_string().contains("different content"), "{error}");
+
+        for dir in [first, second, dest] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn an_earlier_run_both_shards_continue_is_counted_once() {
+        let shard = |uid: &str, status: Status| {
+            let items = vec![item(uid, status, 200), item("earlier", Status::Passed, 100)];
+            seed(&[], History::from([("test".to_string(), entry(items))]))
+        };
+        let (first, second) = (shard("a", Status::Passed), shard("b", Status::Failed));
+        let dest = temp_dir();
+
+        merge_results_dirs(&[&first, &second], &dest).unwrap();
+
+        let history = read_history(&dest).unwrap().unwrap();
+        let entry = &history["test"];
+        assert_eq!(entry.items.len(), 3);
+        assert_eq!(entry.statistic.total, 3);
+        assert_eq!(entry.statistic.passed, 2);
+        assert_eq!(entry.statistic.failed, 1);
+
+        for dir in [first, second, dest] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn merging_the_same_shard_twice_does_not_inflate_the_history() {
+        let shard = seed(
+            &[],
+            History::from([(
+                "test".to_string(),
+                entry(vec![item("a", Status::Passed, 100)]),
+            )]),
+        );
+        let dest = temp_dir();
+
+        merge_results_dirs(&[&shard, &shard], &dest).unwrap();
+
+        let history = read_history(&dest).unwrap().unwrap();
+        assert_eq!(history["test"].items.len(), 1);
+        assert_eq!(history["test"].statistic.total, 1);
+
+        for dir in [shard, dest] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
@@ -1,8 +1,49 @@
+use md5::Md5;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::OnceLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use uuid::Uuid;
 
+/// A wall-clock reading and the instant it was taken at, the first time [`unix_millis`]
+/// runs; times before the epoch are estimated from it with the monotonic clock
+static ANCHOR: OnceLock<(Instant, Duration)> = OnceLock::new();
+
+fn anchor(fallback: Duration) -> (Instant, Duration) {
+    *ANCHOR.get_or_init(|| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(fallback);
+        (Instant::now(), now)
+    })
+}
+
+/// Converts a time to milliseconds since the UNIX epoch, the unit of every Allure timestamp.
+///
+/// A time before the epoch, e.g. from a badly set clock, is reported with a warning as the
+/// current time, measured with the monotonic clock from the first conversion, instead of
+/// as 1970.
+pub fn unix_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => {
+            anchor(elapsed);
+            elapsed.as_millis() as i64
+        }
+        Err(e) => {
+            let (instant, since_epoch) = anchor(Duration::ZERO);
+            let fallback = (since_epoch + instant.elapsed()).as_millis() as i64;
+            eprintln!(
+                "tanu-allure: timestamp is {:?} before the UNIX epoch, using {fallback} instead",
+                e.duration()
+            );
+            fallback
+        }
+    }
+}
+
 /// Represents an Allure test result file.
 #[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -52,10 +93,10 @@ pub struct TestResult {
     /// The stage in the lifecycle of the test or step.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stage: Option<Stage>,
-    /// The time when the execution of the test or step started, in UNIX timestamp format.
+    /// The time when the execution of the test or step started, in milliseconds since the UNIX epoch.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub start: Option<i64>,
-    /// The time when the execution of the test or step finished, in UNIX timestamp format.
+    /// The time when the execution of the test or step finished, in milliseconds since the UNIX epoch.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop: Option<i64>,
     /// An array of test steps.
@@ -64,7 +105,7 @@ pub struct TestResult {
 }
 
 /// Represents a link in an Allure test result.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Link {
     /// The type of the link, e.g., "issue" or "tms".
@@ -75,6 +116,26 @@ pub struct Link {
     pub url: url::Url,
 }
 
+impl Link {
+    /// Creates a link to an issue in a bug tracker.
+    pub fn issue(name: impl Into<String>, url: url::Url) -> Self {
+        Link {
+            r#type: "issue".to_string(),
+            name: name.into(),
+            url,
+        }
+    }
+
+    /// Creates a link to a test case in a test management system.
+    pub fn tms(name: impl Into<String>, url: url::Url) -> Self {
+        Link {
+            r#type: "tms".to_string(),
+            name: name.into(),
+            url,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Labels {
@@ -103,6 +164,59 @@ pub struct Labels {
     pub labels: HashMap<String, String>,
 }
 
+impl From<Labels> for Vec<Label> {
+    /// Emits a label for every field that is set, followed by the other entries sorted by
+    /// name, each made with [`Label::custom`].
+    fn from(labels: Labels) -> Self {
+        let typed = [
+            labels.tag.map(Label::Tag),
+            labels.severity.map(Label::Severity),
+            labels.owner.map(Label::Owner),
+            labels.epic.map(Label::Epic),
+            labels.feature.map(Label::Feature),
+            labels.story.map(Label::Story),
+            labels.parent_suite.map(Label::ParentSuite),
+            labels.suite.map(Label::Suite),
+            labels.sub_suite.map(Label::SubSuite),
+            labels.package.map(Label::Package),
+        ];
+        let mut custom: Vec<_> = labels.labels.into_iter().collect();
+        custom.sort();
+
+        typed
+            .into_iter()
+            .flatten()
+            .chain(
+                custom
+                    .into_iter()
+                    .map(|(name, value)| Label::custom(name, value)),
+            )
+            .collect()
+    }
+}
+
+/// Label names with a meaning in Allure, in the spelling Allure expects.
+pub const RESERVED_LABEL_NAMES: &[&str] = &[
+    "tag",
+    "severity",
+    "owner",
+    "epic",
+    "feature",
+    "story",
+    "parentSuite",
+    "suite",
+    "subSuite",
+    "package",
+    "host",
+    "thread",
+    "testClass",
+    "testMethod",
+    "framework",
+    "language",
+    "layer",
+    "ALLURE_ID",
+];
+
 /// Represents a label in an Allure test result.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "name", content = "value")]
@@ -128,11 +242,52 @@ pub enum Label {
 }
 
 impl Label {
-    /// Creates a custom label with the given name and value
+    /// Creates a label with the given name and value.
+    ///
+    /// The name is trimmed. Names Allure reserves, listed in [`RESERVED_LABEL_NAMES`], are
+    /// matched case-insensitively and yield the corresponding label, so `Severity` becomes
+    /// a `severity` label instead of one Allure ignores. A label without a name cannot be
+    /// shown, so an empty name yields a `tag` label with the value.
     pub fn custom(name: impl Into<String>, value: impl Into<String>) -> Self {
-        Label::Custom {
-            name: name.into(),
-            value: value.into(),
+        let name = name.into();
+        let value = value.into();
+        let name = name.trim();
+        if name.is_empty() {
+            return Label::Tag(value);
+        }
+        let name = RESERVED_LABEL_NAMES
+            .iter()
+            .find(|reserved| reserved.eq_ignore_ascii_case(name))
+            .map_or(name, |reserved| reserved);
+        match name {
+            "tag" => Label::Tag(value),
+            "severity" => Label::Severity(value),
+            "owner" => Label::Owner(value),
+            "epic" => Label::Epic(value),
+            "feature" => Label::Feature(value),
+            "story" => Label::Story(value),
+            "parentSuite" => Label::ParentSuite(value),
+            "suite" => Label::Suite(value),
+            "subSuite" => Label::SubSuite(value),
+            "package" => Label::Package(value),
+            "host" => Label::Host(value),
+            "thread" => Label::Thread(value),
+            _ => Label::Custom {
+                name: name.to_string(),
+                value,
+            },
+        }
+    }
+
+    /// Converts a test tag into a label.
+    ///
+    /// `severity:`, `owner:`, `epic:`, `feature:` and `story:` prefixes map to the
+    /// corresponding labels, any other `name:value` tag to a label as described in
+    /// [`custom`](Self::custom), and a tag without a prefix to a `tag` label.
+    pub fn from_tag(tag: &str) -> Self {
+        match tag.split_once(':') {
+            Some((name, value)) => Label::custom(name, value),
+            None => Label::Tag(tag.to_string()),
         }
     }
 }
@@ -191,6 +346,18 @@ pub enum Status {
     Unknown,
 }
 
+impl Status {
+    /// How strongly the status signals a problem when rolling up step statuses
+    fn severity(&self) -> u8 {
+        match self {
+            Status::Failed => 3,
+            Status::Broken => 2,
+            Status::Passed => 1,
+            Status::Skipped | Status::Unknown => 0,
+        }
+    }
+}
+
 /// Represents detailed information about the test status.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -243,10 +410,10 @@ pub struct Step {
     /// The stage in the lifecycle of the step.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stage: Option<Stage>,
-    /// The time when the execution of the step started, in UNIX timestamp format.
+    /// The time when the execution of the step started, in milliseconds since the UNIX epoch.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub start: Option<i64>,
-    /// The time when the execution of the step finished, in UNIX timestamp format.
+    /// The time when the execution of the step finished, in milliseconds since the UNIX epoch.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop: Option<i64>,
     /// An array of sub-steps within this step.
@@ -254,6 +421,237 @@ pub struct Step {
     pub steps: Vec<Step>,
 }
 
+impl Step {
+    /// Creates a parent step around `steps`, spanning their timing and carrying the most
+    /// severe of their statuses.
+    pub fn group(name: impl Into<String>, steps: Vec<Step>) -> Self {
+        let status = steps.iter().fold(Status::Passed, |acc, step| {
+            if step.status.severity() > acc.severity() {
+                step.status.clone()
+            } else {
+                acc
+            }
+        });
+        let mut group = Step {
+            name: name.into(),
+            parameters: Vec::new(),
+            attachments: Vec::new(),
+            status,
+            status_details: None,
+            stage: Some(Stage::Finished),
+            start: None,
+            stop: None,
+            steps,
+        };
+        group.roll_up_timing();
+        group
+    }
+
+    /// Widens the step's `start`/`stop` to span all of its sub-steps, recursively.
+    ///
+    /// Steps without sub-steps keep their own timing.
+    pub fn roll_up_timing(&mut self) {
+        for step in &mut self.steps {
+            step.roll_up_timing();
+        }
+        if let Some(start) = self.steps.iter().filter_map(|step| step.start).min() {
+            self.start = Some(self.start.map_or(start, |own| own.min(start)));
+        }
+        if let Some(stop) = self.steps.iter().filter_map(|step| step.stop).max() {
+            self.stop = Some(self.stop.map_or(stop, |own| own.max(stop)));
+        }
+    }
+}
+
+/// Represents an Allure container file, which wraps test results with the
+/// setup and teardown fixtures that ran around them.
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TestResultContainer {
+    /// A unique identifier of the container.
+    pub uuid: Uuid,
+    /// The name of the container.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The uuids of the test results the fixtures apply to.
+    #[serde(default)]
+    pub children: Vec<Uuid>,
+    /// Fixtures that ran before the tests.
+    #[serde(default)]
+    pub befores: Vec<FixtureResult>,
+    /// Fixtures that ran after the tests.
+    #[serde(default)]
+    pub afters: Vec<FixtureResult>,
+    /// The time when the first fixture or test started, in milliseconds since the UNIX epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    /// The time when the last fixture or test finished, in milliseconds since the UNIX epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<i64>,
+}
+
+/// Represents a setup or teardown fixture in an Allure container.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureResult {
+    /// The name of the fixture.
+    pub name: String,
+    /// An array of parameters added to the fixture.
+    #[serde(default)]
+    pub parameters: Vec<Parameter>,
+    /// An array of attachments added to the fixture.
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// The status with which the fixture finished.
+    pub status: Status,
+    /// Detailed information about the fixture status.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_details: Option<StatusDetails>,
+    /// The stage in the lifecycle of the fixture.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<Stage>,
+    /// The time when the execution of the fixture started, in milliseconds since the UNIX epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    /// The time when the execution of the fixture finished, in milliseconds since the UNIX epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<i64>,
+    /// An array of steps within the fixture.
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+/// Represents a category in `categories.json`, which sorts failed and broken results into
+/// defect buckets in the report.
+///
+/// A result falls into the first category whose statuses and regular expressions all match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Category {
+    /// The name of the category.
+    pub name: String,
+    /// The statuses a result must have to fall into the category.
+    #[serde(default)]
+    pub matched_statuses: Vec<Status>,
+    /// A regular expression the status message must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_regex: Option<String>,
+    /// A regular expression the status trace must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_regex: Option<String>,
+}
+
+impl Category {
+    /// Creates a category matching every result with one of the given statuses.
+    pub fn new(name: impl Into<String>, matched_statuses: Vec<Status>) -> Self {
+        Category {
+            name: name.into(),
+            matched_statuses,
+            message_regex: None,
+            trace_regex: None,
+        }
+    }
+
+    /// The categories used by `AllureReporter::with_default_categories`: failed checks are
+    /// product defects, broken tests are test defects.
+    pub fn defaults() -> Vec<Category> {
+        vec![
+            Category::new("Product defects", vec![Status::Failed]),
+            Category::new("Test defects", vec![Status::Broken]),
+        ]
+    }
+}
+
+/// Represents `executor.json`, which describes the CI build that produced the results in the
+/// report's "Executors" widget.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Executor {
+    /// The name of the CI system, e.g. "GitHub Actions".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The type of the CI system, which selects the icon, e.g. "github" or "jenkins".
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>,
+    /// The URL of the CI system.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The number of the build, used to order builds in history.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_order: Option<i64>,
+    /// The name of the build.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_name: Option<String>,
+    /// The URL of the build.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_url: Option<String>,
+    /// The URL of the published report.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report_url: Option<String>,
+    /// The name of the report.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report_name: Option<String>,
+}
+
+impl Executor {
+    /// Detects the CI system from its environment variables.
+    ///
+    /// Recognizes GitHub Actions, GitLab CI and Jenkins, and returns `None` elsewhere.
+    pub fn from_env() -> Option<Self> {
+        let var = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+        let build_order = |key: &str| var(key).and_then(|value| value.parse().ok());
+
+        if var("GITHUB_ACTIONS").as_deref() == Some("true") {
+            let server = var("GITHUB_SERVER_URL").unwrap_or_else(|| "https://github.com".into());
+            let build_url = match (var("GITHUB_REPOSITORY"), var("GITHUB_RUN_ID")) {
+                (Some(repository), Some(run_id)) => {
+                    Some(format!("{server}/{repository}/actions/runs/{run_id}"))
+                }
+                _ => None,
+            };
+            let build_name = match (var("GITHUB_WORKFLOW"), var("GITHUB_RUN_NUMBER")) {
+                (Some(workflow), Some(number)) => Some(format!("{workflow} #{number}")),
+                (workflow, _) => workflow,
+            };
+            return Some(Executor {
+                name: Some("GitHub Actions".into()),
+                r#type: Some("github".into()),
+                url: Some(server),
+                build_order: build_order("GITHUB_RUN_NUMBER"),
+                build_name,
+                build_url,
+                ..Default::default()
+            });
+        }
+
+        if var("GITLAB_CI").is_some() {
+            return Some(Executor {
+                name: Some("GitLab CI".into()),
+                r#type: Some("gitlab".into()),
+                url: var("CI_SERVER_URL"),
+                build_order: build_order("CI_PIPELINE_IID"),
+                build_name: var("CI_PIPELINE_IID").map(|iid| format!("Pipeline #{iid}")),
+                build_url: var("CI_PIPELINE_URL"),
+                ..Default::default()
+            });
+        }
+
+        if let Some(url) = var("JENKINS_URL") {
+            return Some(Executor {
+                name: Some("Jenkins".into()),
+                r#type: Some("jenkins".into()),
+                url: Some(url),
+                build_order: build_order("BUILD_NUMBER"),
+                build_name: var("BUILD_TAG").or_else(|| var("JOB_NAME")),
+                build_url: var("BUILD_URL"),
+                ..Default::default()
+            });
+        }
+
+        None
+    }
+}
+
 // ============================================================================
 // History types for tracking test execution history across runs
 // ============================================================================
@@ -281,13 +679,40 @@ impl HistoryStatistic {
         }
         self.total += 1;
     }
+
+    /// Takes back a run recorded with [`record`](Self::record), e.g. one already counted
+    /// elsewhere
+    pub fn forget(&mut self, status: &Status) {
+        let count = match status {
+            Status::Failed => &mut self.failed,
+            Status::Broken => &mut self.broken,
+            Status::Skipped => &mut self.skipped,
+            Status::Passed => &mut self.passed,
+            Status::Unknown => &mut self.unknown,
+        };
+        *count = count.saturating_sub(1);
+        self.total = self.total.saturating_sub(1);
+    }
+
+    /// Adds the counts of `other`, e.g. of the same test in another results directory
+    pub fn merge(&mut self, other: &HistoryStatistic) {
+        self.failed += other.failed;
+        self.broken += other.broken;
+        self.skipped += other.skipped;
+        self.passed += other.passed;
+        self.unknown += other.unknown;
+        self.total += other.total;
+    }
 }
 
 /// Timing information for a history item
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryTime {
+    /// Milliseconds since the UNIX epoch.
     pub start: i64,
+    /// Milliseconds since the UNIX epoch.
     pub stop: i64,
+    /// Milliseconds.
     pub duration: i64,
 }
 
@@ -317,6 +742,64 @@ pub type History = HashMap<String, HistoryEntry>;
 /// Maximum number of history items to keep per test
 pub const MAX_HISTORY_ITEMS: usize = 20;
 
+/// One run in a trend file such as `history/duration-trend.json`, newest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrendItem<T> {
+    pub data: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_order: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report_name: Option<String>,
+}
+
+/// Data point of `history/duration-trend.json`: wall-clock time of the whole run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DurationTrendData {
+    pub duration: i64,
+}
+
+/// Data point of `history/retry-trend.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryTrendData {
+    /// Results written in the run, retried attempts included
+    pub run: u32,
+    /// Attempts that were retried
+    pub retry: u32,
+}
+
+/// Maximum number of runs to keep in each trend file
+pub const MAX_TREND_ITEMS: usize = 20;
+
+/// Which parts of a test's identity make up its history_id.
+///
+/// Everything participates by default. Leaving out `project` aggregates the history of the
+/// same test across projects; leaving out `parameters` merges all parameterizations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryIdConfig {
+    /// The tanu project name.
+    pub project: bool,
+    /// The module the test is defined in.
+    pub module: bool,
+    /// The test name, including the arguments of parameterized tests.
+    pub test_name: bool,
+    /// Non-excluded parameters only; excluded ones never participate.
+    pub parameters: bool,
+}
+
+impl Default for HistoryIdConfig {
+    fn default() -> Self {
+        HistoryIdConfig {
+            project: true,
+            module: true,
+            test_name: true,
+            parameters: true,
+        }
+    }
+}
+
 /// Generates a deterministic history_id from test identity.
 ///
 /// The history_id is a SHA-256 hash of:
@@ -329,25 +812,89 @@ pub fn generate_history_id(
     module: &str,
     test_name: &str,
     parameters: &[Parameter],
+) -> String {
+    generate_history_id_with(
+        project,
+        module,
+        test_name,
+        parameters,
+        &HistoryIdConfig::default(),
+    )
+}
+
+/// Like [`generate_history_id`], hashing only the components enabled in `config`.
+///
+/// With the default config both produce the same id.
+pub fn generate_history_id_with(
+    project: &str,
+    module: &str,
+    test_name: &str,
+    parameters: &[Parameter],
+    config: &HistoryIdConfig,
 ) -> String {
     let mut hasher = Sha256::new();
-    hasher.update(format!("{project}::{module}::{test_name}"));
+    let identity: Vec<&str> = [
+        (config.project, project),
+        (config.module, module),
+        (config.test_name, test_name),
+    ]
+    .into_iter()
+    .filter_map(|(included, component)| included.then_some(component))
+    .collect();
+    hasher.update(identity.join("::"));
 
-    // Include non-excluded parameters (sorted for determinism)
-    let mut params: Vec<_> = parameters
-        .iter()
-        .filter(|p| p.excluded != Some(true))
-        .map(|p| (&p.name, &p.value))
-        .collect();
-    params.sort_by_key(|(name, _)| *name);
+    if config.parameters {
+        // Include non-excluded parameters (sorted for determinism)
+        let mut params: Vec<_> = parameters
+            .iter()
+            .filter(|p| p.excluded != Some(true))
+            .map(|p| (&p.name, &p.value))
+            .collect();
+        params.sort_by_key(|(name, _)| *name);
 
-    for (name, value) in params {
-        hasher.update(format!("::{name}={value}"));
+        for (name, value) in params {
+            hasher.update(format!("::{name}={value}"));
+        }
     }
 
     format!("{:x}", hasher.finalize())
 }
 
+/// Hash algorithm used to derive `testCaseId` from a test's identity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgo {
+    /// 32 hex characters of the MD5 of the full name, `project::module#test`, matching
+    /// what Allure's own integrations emit.
+    #[default]
+    Md5,
+    /// 64 hex characters of the SHA-256 of `project::module::test`, the identity the
+    /// history_id is derived from.
+    Sha256,
+}
+
+/// Generates a deterministic test_case_id from the test's identity, as described for each
+/// [`HashAlgo`].
+///
+/// Unlike the history_id, parameters are not included, so every parameter set of a test
+/// shares the same test_case_id.
+pub fn generate_test_case_id(
+    project: &str,
+    module: &str,
+    test_name: &str,
+    algo: HashAlgo,
+) -> String {
+    match algo {
+        HashAlgo::Md5 => format!(
+            "{:x}",
+            Md5::digest(format!("{project}::{module}#{test_name}"))
+        ),
+        HashAlgo::Sha256 => format!(
+            "{:x}",
+            Sha256::digest(format!("{project}::{module}::{test_name}"))
+        ),
+    }
+}
+
 impl TestResult {
     /// Creates a new TestResult with a random UUID v4.
     pub fn new(name: String) -> Self {
@@ -374,29 +921,557 @@ impl TestResult {
 
     /// Sets the start time to the current time
     pub fn start(&mut self) {
-        self.start = Some(
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_millis() as i64,
-        );
+        self.start = Some(unix_millis(SystemTime::now()));
         self.stage = Some(Stage::Running);
     }
 
     /// Sets the stop time to the current time
     pub fn stop(&mut self) {
-        self.stop = Some(
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_millis() as i64,
-        );
+        self.stop = Some(unix_millis(SystemTime::now()));
         self.stage = Some(Stage::Finished);
     }
 
-    /// Generates a history_id from test name and parameters
-    pub fn set_history_id(&mut self) {
-        // Simple implementation - in real code you might want to hash name + parameters
-        self.history_id = format!("{}-history", self.name);
+    /// Returns the execution time in milliseconds, if both `start` and `stop` are set.
+    pub fn duration_ms(&self) -> Option<i64> {
+        Some(self.stop? - self.start?)
+    }
+
+    /// Returns the status rolled up from the result and all of its steps.
+    ///
+    /// A failed or broken step at any depth makes the effective status at least as severe,
+    /// while skipped and unknown steps are ignored. The stored `status` stays authoritative;
+    /// this is only a derived view.
+    pub fn effective_status(&self) -> Status {
+        fn worst(status: Status, steps: &[Step]) -> Status {
+            steps.iter().fold(status, |acc, step| {
+                let step_status = worst(step.status.clone(), &step.steps);
+                if step_status.severity() > acc.severity() {
+                    step_status
+                } else {
+                    acc
+                }
+            })
+        }
+        worst(self.status.clone(), &self.steps)
+    }
+
+    /// Sets the history_id from the given project and module and the result's name and
+    /// parameters, the same way the reporter derives it with [`generate_history_id`].
+    pub fn set_history_id(&mut self, project: &str, module: &str) {
+        self.history_id = generate_history_id(project, module, &self.name, &self.parameters);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_millis_replaces_pre_epoch_times_with_the_current_time() {
+        let before = unix_millis(SystemTime::now());
+        let converted = unix_millis(UNIX_EPOCH - Duration::from_secs(1));
+        assert!(converted >= before, "{converted} < {before}");
+    }
+
+    #[test]
+    fn test_case_ids_are_lowercase_hex_of_the_algorithm_length() {
+        for (algo, len) in [(HashAlgo::Md5, 32), (HashAlgo::Sha256, 64)] {
+            let id = generate_test_case_id("project", "module", "test", algo);
+            assert_eq!(id.len(), len, "{algo:?}");
+            assert!(
+                id.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')),
+                "{algo:?}: {id}"
+            );
+            assert_eq!(id, generate_test_case_id("project", "module", "test", algo));
+        }
+        assert_eq!(
+            generate_test_case_id("project", "module", "test", HashAlgo::default()),
+            format!("{:x}", Md5::digest("project::module#test"))
+        );
+        assert_eq!(
+            generate_test_case_id("project", "module", "test", HashAlgo::Sha256),
+            format!("{:x}", Sha256::digest("project::module::test"))
+        );
+    }
+
+    #[test]
+    fn parameter_sets_of_a_test_share_a_test_case_id_but_not_a_history_id() {
+        let parameters = |value: &str| {
+            vec![Parameter {
+                name: "user".to_string(),
+                value: value.to_string(),
+                excluded: None,
+                mode: None,
+            }]
+        };
+        let (alice, bob) = (parameters("alice"), parameters("bob"));
+        assert_ne!(
+            generate_history_id("project", "module", "login", &alice),
+            generate_history_id("project", "module", "login", &bob)
+        );
+
+        let test_case_id = |_: &[Parameter]| {
+            generate_test_case_id("project", "module", "login", HashAlgo::default())
+        };
+        assert_eq!(test_case_id(&alice), test_case_id(&bob));
+        assert_ne!(
+            test_case_id(&alice),
+            generate_test_case_id("project", "module", "logout", HashAlgo::default())
+        );
+    }
+
+    #[test]
+    fn reserved_label_names_match_case_insensitively_after_trimming() {
+        assert_eq!(
+            Label::custom(" Severity ", "critical"),
+            Label::Severity("critical".to_string())
+        );
+        assert_eq!(
+            Label::custom("PARENTSUITE", "api"),
+            Label::ParentSuite("api".to_string())
+        );
+        assert_eq!(
+            Label::custom(" team ", "payments"),
+            Label::Custom {
+                name: "team".to_string(),
+                value: "payments".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn a_label_without_a_name_becomes_a_tag() {
+        for name in ["", "   "] {
+            assert_eq!(
+                Label::custom(name, "smoke"),
+                Label::Tag("smoke".to_string())
+            );
+        }
+    }
+
+    fn parameter(name: &str, value: &str) -> Parameter {
+        Parameter {
+            name: name.to_string(),
+            value: value.to_string(),
+            excluded: None,
+            mode: None,
+        }
+    }
+
+    #[test]
+    fn a_history_id_is_stable_across_runs() {
+        // Pinned, so that a change to the hashing, which would start every test's history
+        // anew, cannot go unnoticed
+        assert_eq!(
+            generate_history_id("project", "module", "login", &[]),
+            "f64b7f88b1a7411d055d1a05dd5ea4b0e367ee92e5879b62ad2382e79d5fda32"
+        );
+        assert_eq!(
+            generate_history_id("project", "module", "login", &[parameter("user", "alice")]),
+            "6f836e9f114cbd6dc73a3cf9e616b6552d19060e2a6fd2571e110256515d0eed"
+        );
+        let (user, role) = (|| parameter("user", "alice"), || parameter("role", "admin"));
+        assert_eq!(
+            generate_history_id("project", "module", "login", &[user(), role()]),
+            generate_history_id("project", "module", "login", &[role(), user()])
+        );
+    }
+
+    #[test]
+    fn different_parameters_give_different_history_ids() {
+        let id = |parameters: &[Parameter]| {
+            generate_history_id("project", "module", "login", parameters)
+        };
+        let alice = id(&[parameter("user", "alice")]);
+        assert_ne!(alice, id(&[parameter("user", "bob")]));
+        assert_ne!(alice, id(&[parameter("name", "alice")]));
+        assert_ne!(alice, id(&[]));
+    }
+
+    #[test]
+    fn an_excluded_parameter_leaves_the_history_id_unchanged() {
+        let excluded = Parameter {
+            excluded: Some(true),
+            ..parameter("Project", "staging")
+        };
+        let user = || parameter("user", "alice");
+        assert_eq!(
+            generate_history_id("project", "module", "login", &[user(), excluded]),
+            generate_history_id("project", "module", "login", &[user()])
+        );
+    }
+
+    fn step(status: Status, start: Option<i64>, stop: Option<i64>, steps: Vec<Step>) -> Step {
+        Step {
+            status,
+            start,
+            stop,
+            steps,
+            ..Step::group("step", Vec::new())
+        }
+    }
+
+    #[test]
+    fn timing_rolls_up_through_every_level() {
+        let leaf = step(Status::Passed, Some(150), Some(400), Vec::new());
+        let middle = step(Status::Passed, None, None, vec![leaf]);
+        let mut root = step(Status::Passed, Some(100), Some(200), vec![middle]);
+        root.roll_up_timing();
+
+        assert_eq!((root.start, root.stop), (Some(100), Some(400)));
+        let middle = &root.steps[0];
+        assert_eq!((middle.start, middle.stop), (Some(150), Some(400)));
+    }
+
+    #[test]
+    fn a_parent_keeps_a_start_earlier_than_its_steps() {
+        let child = step(Status::Passed, Some(300), Some(350), Vec::new());
+        let mut parent = step(Status::Passed, Some(200), Some(500), vec![child]);
+        parent.roll_up_timing();
+        assert_eq!((parent.start, parent.stop), (Some(200), Some(500)));
+    }
+
+    #[test]
+    fn a_duration_needs_both_start_and_stop() {
+        let mut result = TestResult::new("timed".to_string());
+        assert_eq!(result.duration_ms(), None);
+        result.start = Some(1_000);
+        assert_eq!(result.duration_ms(), None);
+        result.stop = Some(1_250);
+        assert_eq!(result.duration_ms(), Some(250));
+        result.start = None;
+        assert_eq!(result.duration_ms(), None);
+    }
+
+    #[test]
+    fn a_nested_failed_step_raises_the_effective_status() {
+        let mut result = TestResult::new("nested".to_string());
+        result.status = Status::Passed;
+        let failed = step(Status::Failed, None, None, Vec::new());
+        let skipped = step(Status::Skipped, None, None, Vec::new());
+        result.steps = vec![step(Status::Passed, None, None, vec![skipped, failed])];
+
+        assert_eq!(result.effective_status(), Status::Failed);
+        assert_eq!(result.status, Status::Passed);
+    }
+
+    #[test]
+    fn skipped_steps_leave_the_effective_status_alone() {
+        let mut result = TestResult::new("skipping".to_string());
+        result.status = Status::Passed;
+        result.steps = vec![step(Status::Skipped, None, None, Vec::new())];
+        assert_eq!(result.effective_status(), Status::Passed);
+    }
+
+    #[test]
+    fn labels_convert_in_field_order_then_sorted_custom_entries() {
+        let labels = Labels {
+            suite: Some("users".to_string()),
+            severity: Some("critical".to_string()),
+            tag: Some("smoke".to_string()),
+            labels: HashMap::from([
+                ("team".to_string(), "payments".to_string()),
+                ("layer".to_string(), "api".to_string()),
+                ("Owner".to_string(), "alice".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let custom = |name: &str, value: &str| Label::Custom {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+
+        assert_eq!(
+            Vec::<Label>::from(labels),
+            [
+                Label::Tag("smoke".to_string()),
+                Label::Severity("critical".to_string()),
+                Label::Suite("users".to_string()),
+                Label::Owner("alice".to_string()),
+                custom("layer", "api"),
+                custom("team", "payments"),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaving_out_the_project_shares_a_history_across_projects() {
+        let config = HistoryIdConfig {
+            project: false,
+            ..Default::default()
+        };
+        let id = |project: &str, config: &HistoryIdConfig| {
+            generate_history_id_with(project, "module", "login", &[], config)
+        };
+        assert_eq!(id("staging", &config), id("production", &config));
+        let default = HistoryIdConfig::default();
+        assert_ne!(id("staging", &default), id("production", &default));
+    }
+
+    #[test]
+    fn the_default_config_hashes_like_generate_history_id() {
+        let parameters = [parameter("user", "alice")];
+        assert_eq!(
+            generate_history_id_with(
+                "project",
+                "module",
+                "login",
+                &parameters,
+                &HistoryIdConfig::default()
+            ),
+            generate_history_id("project", "module", "login", &parameters)
+        );
+    }
+
+    /// Every variable `Executor::from_env` reads
+    const CI_VARS: &[&str] = &[
+        "GITHUB_ACTIONS",
+        "GITHUB_SERVER_URL",
+        "GITHUB_REPOSITORY",
+        "GITHUB_RUN_ID",
+        "GITHUB_WORKFLOW",
+        "GITHUB_RUN_NUMBER",
+        "GITLAB_CI",
+        "CI_SERVER_URL",
+        "CI_PIPELINE_IID",
+        "CI_PIPELINE_URL",
+        "JENKINS_URL",
+        "BUILD_NUMBER",
+        "BUILD_TAG",
+        "JOB_NAME",
+        "BUILD_URL",
+    ];
+
+    /// Serializes the tests that change the process environment
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    /// Detects the executor with exactly `vars` of [`CI_VARS`] set, restoring the
+    /// environment afterwards
+    fn executor_with(vars: &[(&str, &str)]) -> Option<Executor> {
+        let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let saved: Vec<_> = CI_VARS
+            .iter()
+            .map(|key| (*key, std::env::var_os(key)))
+            .collect();
+        for key in CI_VARS {
+            std::env::remove_var(key);
+        }
+        for (key, value) in vars {
+            std::env::set_var(key, value);
+        }
+        let executor = Executor::from_env();
+        for (key, value) in saved {
+            match value {
+                Some(value) => std::env::set_var(key, value),
+                None => std::env::remove_var(key),
+            }
+        }
+        executor
+    }
+
+    #[test]
+    fn github_actions_is_detected_with_its_run() {
+        let executor = executor_with(&[
+            ("GITHUB_ACTIONS", "true"),
+            ("GITHUB_REPOSITORY", "tanu-rs/tanu"),
+            ("GITHUB_RUN_ID", "123"),
+            ("GITHUB_WORKFLOW", "CI"),
+            ("GITHUB_RUN_NUMBER", "42"),
+        ])
+        .unwrap();
+        assert_eq!(executor.r#type.as_deref(), Some("github"));
+        assert_eq!(executor.url.as_deref(), Some("https://github.com"));
+        assert_eq!(executor.build_order, Some(42));
+        assert_eq!(executor.build_name.as_deref(), Some("CI #42"));
+        assert_eq!(
+            executor.build_url.as_deref(),
+            Some("https://github.com/tanu-rs/tanu/actions/runs/123")
+        );
+    }
+
+    #[test]
+    fn gitlab_ci_is_detected_with_its_pipeline() {
+        let executor = executor_with(&[
+            ("GITLAB_CI", "true"),
+            ("CI_SERVER_URL", "https://gitlab.example.com"),
+            ("CI_PIPELINE_IID", "7"),
+            (
+                "CI_PIPELINE_URL",
+                "https://gitlab.example.com/p/-/pipelines/99",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(executor.r#type.as_deref(), Some("gitlab"));
+        assert_eq!(executor.url.as_deref(), Some("https://gitlab.example.com"));
+        assert_eq!(executor.build_order, Some(7));
+        assert_eq!(executor.build_name.as_deref(), Some("Pipeline #7"));
+        assert_eq!(
+            executor.build_url.as_deref(),
+            Some("https://gitlab.example.com/p/-/pipelines/99")
+        );
+    }
+
+    #[test]
+    fn jenkins_is_detected_with_its_build() {
+        let executor = executor_with(&[
+            ("JENKINS_URL", "https://jenkins.example.com/"),
+            ("BUILD_NUMBER", "15"),
+            ("JOB_NAME", "api-tests"),
+            ("BUILD_URL", "https://jenkins.example.com/job/api-tests/15/"),
+        ])
+        .unwrap();
+        assert_eq!(executor.r#type.as_deref(), Some("jenkins"));
+        assert_eq!(
+            executor.url.as_deref(),
+            Some("https://jenkins.example.com/")
+        );
+        assert_eq!(executor.build_order, Some(15));
+        assert_eq!(executor.build_name.as_deref(), Some("api-tests"));
+        assert_eq!(
+            executor.build_url.as_deref(),
+            Some("https://jenkins.example.com/job/api-tests/15/")
+        );
+    }
+
+    #[test]
+    fn no_executor_is_detected_outside_of_ci() {
+        assert!(executor_with(&[]).is_none());
+        assert!(executor_with(&[("GITHUB_ACTIONS", "false")]).is_none());
+    }
+
+    #[test]
+    fn tags_map_to_their_labels() {
+        for (tag, label) in [
+            ("severity:critical", Label::Severity("critical".to_string())),
+            ("owner:alice", Label::Owner("alice".to_string())),
+            ("epic:Payments", Label::Epic("Payments".to_string())),
+            ("feature:Refunds", Label::Feature("Refunds".to_string())),
+            (
+                "story:Partial refund",
+                Label::Story("Partial refund".to_string()),
+            ),
+            ("smoke", Label::Tag("smoke".to_string())),
+            (
+                "layer:api",
+                Label::Custom {
+                    name: "layer".to_string(),
+                    value: "api".to_string(),
+                },
+            ),
+            (
+                "url:http://example.com",
+                Label::Custom {
+                    name: "url".to_string(),
+                    value: "http://example.com".to_string(),
+                },
+            ),
+        ] {
+            assert_eq!(Label::from_tag(tag), label, "{tag}");
+        }
+    }
+
+    #[cfg(feature = "json-schema")]
+    mod schema {
+        use super::*;
+
+        const SCHEMA: &str = include_str!("../tests/fixtures/allure-result.schema.json");
+        const SAMPLE: &str = include_str!("../tests/fixtures/sample-result.json");
+
+        fn violations(instance: &serde_json::Value) -> Vec<String> {
+            let schema = serde_json::from_str(SCHEMA).unwrap();
+            let validator = jsonschema::validator_for(&schema).unwrap();
+            validator
+                .iter_errors(instance)
+                .map(|e| format!("{}: {e}", e.instance_path()))
+                .collect()
+        }
+
+        /// A result using every field and label variant the reporter writes
+        fn full_result() -> TestResult {
+            let mut result = TestResult::new("get_user".to_string());
+            result.history_id = generate_history_id("default", "users", "get_user", &[]);
+            result.test_case_id = Some(generate_test_case_id(
+                "default",
+                "users",
+                "get_user",
+                HashAlgo::default(),
+            ));
+            result.full_name = Some("default::users#get_user".to_string());
+            result.description = Some("Fetches a user".to_string());
+            result.description_html = Some("<p>Fetches a user</p>".to_string());
+            result.links = vec![Link::issue(
+                "API-42",
+                "https://jira.example.com/browse/API-42".parse().unwrap(),
+            )];
+            result.labels = vec![
+                Label::Tag("smoke".to_string()),
+                Label::Severity("critical".to_string()),
+                Label::Owner("alice".to_string()),
+                Label::Epic("accounts".to_string()),
+                Label::Feature("users".to_string()),
+                Label::Story("lookup".to_string()),
+                Label::ParentSuite("default".to_string()),
+                Label::Suite("users".to_string()),
+                Label::SubSuite("get".to_string()),
+                Label::Package("default.users".to_string()),
+                Label::Host("ci-runner".to_string()),
+                Label::Thread("worker-0".to_string()),
+                Label::custom("team", "payments"),
+            ];
+            result.parameters = vec![Parameter {
+                name: "token".to_string(),
+                value: "<masked>".to_string(),
+                excluded: Some(true),
+                mode: Some(ParameterMode::Masked),
+            }];
+            result.attachments = vec![Attachment {
+                name: "log".to_string(),
+                source: "log-attachment.txt".to_string(),
+                r#type: "text/plain".to_string(),
+            }];
+            result.start();
+            result.status = Status::Broken;
+            result.status_details = Some(StatusDetails {
+                known: Some(false),
+                muted: Some(false),
+                flaky: Some(true),
+                message: Some("panicked".to_string()),
+                trace: Some("at users.rs:12".to_string()),
+            });
+            result.stage = Some(Stage::Finished);
+            result.stop = result.start;
+            result.steps = vec![Step::group(
+                "GET api.example.com",
+                vec![Step::group("GET /users/1", Vec::new())],
+            )];
+            result
+        }
+
+        #[test]
+        fn the_sample_fixture_conforms_to_the_schema() {
+            let sample = serde_json::from_str(SAMPLE).unwrap();
+            assert_eq!(violations(&sample), Vec::<String>::new());
+        }
+
+        #[test]
+        fn a_generated_result_conforms_to_the_schema() {
+            let result = serde_json::to_value(full_result()).unwrap();
+            assert_eq!(violations(&result), Vec::<String>::new());
+        }
+
+        #[test]
+        fn the_sample_fixture_round_trips_through_test_result() {
+            let result: TestResult = serde_json::from_str(SAMPLE).unwrap();
+            let result = serde_json::to_value(result).unwrap();
+            assert_eq!(violations(&result), Vec::<String>::new());
+        }
+
+        #[test]
+        fn the_schema_rejects_a_misnamed_field() {
+            let mut result = serde_json::to_value(full_result()).unwrap();
+            let history_id = result.as_object_mut().unwrap().remove("historyId").unwrap();
+            result["history_id"] = history_id;
+            assert!(!violations(&result).is_empty());
+        }
     }
 }
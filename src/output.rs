@@ -0,0 +1,181 @@
+//! Capture of diagnostic output printed by tests.
+//!
+//! Rust offers no stable way to intercept `println!`, so tests opt in by writing through
+//! [`stdout()`]/[`stderr()`] or the [`allure_println!`](crate::allure_println) and
+//! [`allure_eprintln!`](crate::allure_eprintln) macros, inside a [`capture`] scope that names
+//! the test. The output is still printed as usual, and when the reporter is configured with
+//! [`AllureReporter::attach_output`](crate::AllureReporter::attach_output) it is also
+//! attached to that test's result.
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{self, Write},
+    sync::{LazyLock, Mutex},
+};
+use tanu_core::{config, ModuleName, ProjectName, TestName};
+
+type CapturedMap = HashMap<(ProjectName, ModuleName, TestName), Captured>;
+
+static CAPTURED: LazyLock<Mutex<CapturedMap>> = LazyLock::new(Default::default);
+
+tokio::task_local! {
+    /// The project, module and name of the test whose output is being captured
+    static CURRENT: (ProjectName, ModuleName, TestName);
+}
+
+/// Output captured for a single test
+#[derive(Default)]
+pub(crate) struct Captured {
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+}
+
+#[derive(Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// A writer that forwards to the process's stdout or stderr and records what was written
+/// for the test of the enclosing [`capture`] scope.
+pub struct OutputWriter {
+    stream: Stream,
+}
+
+/// Returns a writer that prints to stdout and captures the output for the current test.
+pub fn stdout() -> OutputWriter {
+    OutputWriter {
+        stream: Stream::Stdout,
+    }
+}
+
+/// Returns a writer that prints to stderr and captures the output for the current test.
+pub fn stderr() -> OutputWriter {
+    OutputWriter {
+        stream: Stream::Stderr,
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match self.stream {
+            Stream::Stdout => io::stdout().lock().write(buf)?,
+            Stream::Stderr => io::stderr().lock().write(buf)?,
+        };
+        record(self.stream, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.stream {
+            Stream::Stdout => io::stdout().flush(),
+            Stream::Stderr => io::stderr().flush(),
+        }
+    }
+}
+
+/// Runs `fut`, capturing what it prints through this module for the test `test_name` of
+/// `module`, e.g.
+///
+/// ```ignore
+/// #[tanu::test]
+/// async fn get_user() -> eyre::Result<()> {
+///     tanu_allure::output::capture(module_path!(), "get_user", async {
+///         tanu_allure::allure_println!("fetching user 42");
+///         Ok(())
+///     })
+///     .await
+/// }
+/// ```
+///
+/// The project is the one tanu runs the test in. `module` and `test_name` have to match
+/// what tanu reports for the test, or the output is attached to no result.
+pub async fn capture<F: Future>(
+    module: impl Into<ModuleName>,
+    test_name: impl Into<TestName>,
+    fut: F,
+) -> F::Output {
+    let project = config::PROJECT
+        .try_with(|project| project.name.clone())
+        .unwrap_or_default();
+    CURRENT
+        .scope((project, module.into(), test_name.into()), fut)
+        .await
+}
+
+fn record(stream: Stream, bytes: &[u8]) {
+    // Outside of a capture scope there is nothing to attach the output to
+    let Ok(test) = CURRENT.try_with(Clone::clone) else {
+        return;
+    };
+
+    let text = String::from_utf8_lossy(bytes);
+    let mut captured = CAPTURED.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = captured.entry(test).or_default();
+    match stream {
+        Stream::Stdout => entry.stdout.push_str(&text),
+        Stream::Stderr => entry.stderr.push_str(&text),
+    }
+}
+
+/// Removes and returns the output captured so far for the given test.
+pub(crate) fn take(project: &str, module: &str, test_name: &str) -> Option<Captured> {
+    let mut captured = CAPTURED.lock().unwrap_or_else(|e| e.into_inner());
+    captured.remove(&(
+        project.to_string(),
+        module.to_string(),
+        test_name.to_string(),
+    ))
+}
+
+/// Like `println!`, but also captures the line for the current test's Allure result.
+#[macro_export]
+macro_rules! allure_println {
+    ($($arg:tt)*) => {{
+        use ::std::io::Write as _;
+        let _ = ::std::writeln!($crate::output::stdout(), $($arg)*);
+    }};
+}
+
+/// Like `eprintln!`, but also captures the line for the current test's Allure result.
+#[macro_export]
+macro_rules! allure_eprintln {
+    ($($arg:tt)*) => {{
+        use ::std::io::Write as _;
+        let _ = ::std::writeln!($crate::output::stderr(), $($arg)*);
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tanu_core::ProjectConfig;
+
+    #[tokio::test]
+    async fn output_outside_of_a_capture_scope_is_not_recorded() {
+        let project = Arc::new(ProjectConfig {
+            name: "output-test".to_string(),
+            ..Default::default()
+        });
+        config::PROJECT
+            .scope(project, async {
+                crate::allure_println!("outside of a test");
+            })
+            .await;
+        assert!(take("output-test", "", "").is_none());
+    }
+
+    #[tokio::test]
+    async fn output_in_a_capture_scope_is_recorded_for_its_test() {
+        capture("capture-module", "capture-test", async {
+            crate::allure_println!("to stdout");
+            crate::allure_eprintln!("to stderr");
+        })
+        .await;
+        let captured = take("", "capture-module", "capture-test").unwrap();
+        assert_eq!(captured.stdout, "to stdout\n");
+        assert_eq!(captured.stderr, "to stderr\n");
+        assert!(take("", "capture-module", "capture-test").is_none());
+    }
+}
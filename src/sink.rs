@@ -0,0 +1,170 @@
+//! Destinations for the files that make up an Allure results directory.
+//!
+//! [`AllureReporter`](crate::AllureReporter) hands every file it produces to a
+//! [`ResultSink`]. The default [`FilesystemSink`] writes them into `results_dir`; tools that
+//! upload results directly, e.g. to Allure TestOps, can plug in their own sink with
+//! [`AllureReporter::with_sink`](crate::AllureReporter::with_sink) and never touch the disk.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Suffix of the temporary files written next to their destination before being renamed
+/// into place
+pub(crate) const TEMP_FILE_SUFFIX: &str = ".tanu-allure.tmp";
+
+/// Writes `contents` to a temporary file next to `path` and renames it into place, so that
+/// a reader (or a run killed midway) never sees a partially written file.
+///
+/// Falls back to copying if the rename fails because the two paths are on different
+/// devices, e.g. when `path` is itself a mount point.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(
+        ".{}{TEMP_FILE_SUFFIX}",
+        uuid::Uuid::new_v4().simple()
+    ));
+    let temp = PathBuf::from(temp);
+
+    fs::write(&temp, contents)?;
+    let renamed = match fs::rename(&temp, path) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => fs::copy(&temp, path).map(|_| ()),
+        renamed => renamed,
+    };
+    // Best effort: the file is already gone after a successful rename
+    let _ = fs::remove_file(&temp);
+    renamed
+}
+
+/// Receives the files of an Allure results directory.
+///
+/// File names are relative to the results directory and use `/` as separator, e.g.
+/// `{uuid}-result.json` or `history/history.json`. The reporter calls the sink from one task
+/// at a time, but through `&self`, so sinks that collect files need interior mutability.
+pub trait ResultSink: Send {
+    /// Called once before the first test starts; a failure aborts the run.
+    fn prepare(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Writes any file that is neither a test result nor an attachment: containers,
+    /// `environment.properties`, `categories.json`, `executor.json` and the history files.
+    fn write_file(&self, file_name: &str, contents: &[u8]) -> io::Result<()>;
+
+    /// Writes a test result, `{uuid}-result.json` or `{uuid}-result.json.zst`.
+    fn write_result(&self, file_name: &str, contents: &[u8]) -> io::Result<()> {
+        self.write_file(file_name, contents)
+    }
+
+    /// Writes an attachment, `{sha256}-attachment[.gz]`, referenced by a result's `source`. The
+    /// name is derived from the content, so each one is written once per run.
+    fn write_attachment(&self, file_name: &str, contents: &[u8]) -> io::Result<()> {
+        self.write_file(file_name, contents)
+    }
+}
+
+/// Writes results into a directory, creating it and any subdirectories as needed.
+///
+/// Every file is written atomically, through a temporary file renamed into place.
+pub struct FilesystemSink {
+    dir: PathBuf,
+}
+
+impl FilesystemSink {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FilesystemSink { dir: dir.into() }
+    }
+}
+
+impl ResultSink for FilesystemSink {
+    /// Creates the directory and writes and removes a scratch file, so that an unwritable
+    /// directory fails the run once up front instead of every test's result.
+    fn prepare(&self) -> io::Result<()> {
+        let probe = self.dir.join(format!(".probe-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&probe, b""))
+            .and_then(|()| fs::remove_file(&probe))
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("{} is not writable: {e}", self.dir.display()),
+                )
+            })
+    }
+
+    fn write_file(&self, file_name: &str, contents: &[u8]) -> io::Result<()> {
+        let path = self.dir.join(Path::new(file_name));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomically(&path, contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        sync::Arc,
+        thread,
+    };
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("tanu-allure-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    /// A JSON array of `len` numbers, large enough that writing it takes several syscalls
+    fn json(len: usize, value: usize) -> Vec<u8> {
+        serde_json::to_vec(&vec![value; len]).unwrap()
+    }
+
+    #[test]
+    fn a_written_file_is_complete_json_and_leaves_no_temporary_files() {
+        let dir = temp_dir();
+        let sink = FilesystemSink::new(&dir);
+        sink.prepare().unwrap();
+        fs::write(dir.join("0-result.json"), b"[1, 2").unwrap();
+
+        let contents = json(100_000, 7);
+        sink.write_result("0-result.json", &contents).unwrap();
+        sink.write_file("history/history.json", b"{}").unwrap();
+
+        assert_eq!(fs::read(dir.join("0-result.json")).unwrap(), contents);
+        assert_eq!(fs::read(dir.join("history/history.json")).unwrap(), b"{}");
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert!(
+            names.iter().all(|name| !name.ends_with(TEMP_FILE_SUFFIX)),
+            "{names:?}"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_reader_never_sees_a_partially_written_file() {
+        let dir = temp_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.json");
+        write_atomically(&path, &json(20_000, 0)).unwrap();
+
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let (path, done) = (path.clone(), done.clone());
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    let contents = fs::read(&path).unwrap();
+                    serde_json::from_slice::<Vec<usize>>(&contents).unwrap();
+                }
+            })
+        };
+        for value in 1..20 {
+            write_atomically(&path, &json(20_000, value)).unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        reader.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}